@@ -5,15 +5,12 @@ use futures::{
 };
 use par::{
     exchange::{Recv, Send},
-    queue::{Dequeue, Enqueue, Queue},
+    queue::{Broadcaster, Dequeue, Enqueue, Queue},
     runtimes::tokio::fork,
     server::{Connection, Event, Server},
     Dual, Session,
 };
-use std::{
-    collections::{hash_map::Entry, HashMap},
-    io,
-};
+use std::io;
 use tokio::net::{TcpListener, TcpStream};
 use tokio_tungstenite::{
     tungstenite::{self, Message},
@@ -68,16 +65,7 @@ async fn serve(listener: TcpListener) {
         )))
     });
 
-    type Inboxes = HashMap<Nick, Inbox>;
-    fn broadcast(inboxes: &mut Inboxes, line: ChatLine) {
-        let entries = inboxes
-            .drain()
-            .map(|(nick, inbox)| (nick, inbox.push(line.clone())))
-            .collect::<Vec<_>>();
-        inboxes.extend(entries);
-    }
-
-    let mut inboxes = Inboxes::new();
+    let mut inboxes = Broadcaster::<Nick, ChatLine>::new();
 
     while let Some((new_server, transition)) = server.poll().await {
         server = new_server;
@@ -85,38 +73,39 @@ async fn serve(listener: TcpListener) {
         match transition {
             Event::Connect { session: login } => {
                 let (nick, resp) = login.recv().await;
-                let Entry::Vacant(entry) = inboxes.entry(nick.clone()) else {
+                if inboxes.contains_key(&nick) {
                     resp.send1(Err(LoginRefused));
                     continue;
-                };
+                }
                 let (inbox, conn) = resp.choose(Ok).recv().await;
-                entry.insert(inbox);
-                broadcast(&mut inboxes, ChatLine::Info(format!("{} joined", nick.0)));
+                inboxes.insert(nick.clone(), inbox);
+                inboxes.broadcast(ChatLine::Info(format!("{} joined", nick.0)));
                 server.suspend(nick, |c| conn.send1(c));
             }
 
             Event::Resume {
                 session: outbox,
                 data: nick,
+                id,
             } => match outbox.recv1().await {
                 Command::Message(msg) => {
                     let (content, conn) = msg.recv().await;
-                    broadcast(
-                        &mut inboxes,
-                        ChatLine::Message {
-                            from: nick.clone(),
-                            content,
-                        },
-                    );
-                    server.suspend(nick, |c| conn.send1(c));
+                    inboxes.broadcast(ChatLine::Message {
+                        from: nick.clone(),
+                        content,
+                    });
+                    server.resuspend(id, nick, |c| conn.send1(c));
                 }
                 Command::Logout => {
-                    if let Some(inbox) = inboxes.remove(&nick) {
-                        inbox.close1();
-                    }
-                    broadcast(&mut inboxes, ChatLine::Info(format!("{} left", nick.0)));
+                    inboxes.remove(&nick);
+                    inboxes.broadcast(ChatLine::Info(format!("{} left", nick.0)));
                 }
             },
+
+            Event::Expired { data: nick, .. } => {
+                inboxes.remove(&nick);
+                inboxes.broadcast(ChatLine::Info(format!("{} timed out", nick.0)));
+            }
         }
     }
 }
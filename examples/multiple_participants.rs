@@ -1,6 +1,9 @@
+use futures::{channel::mpsc, StreamExt};
 use par::{
     exchange::{Recv, Send},
+    lobby::{Lobby, Ticket},
     runtimes::tokio::fork,
+    server::Proxy,
     Dual,
 };
 use std::time::Duration;
@@ -74,12 +77,14 @@ fn start_playing() -> Game {
     })
 }
 
-fn random_player() -> Player {
-    fork(|mut round: Round| async move {
-        while let Outcome::Draw(next_round) = round.send(random_move()).recv1().await {
-            round = next_round;
-        }
-    })
+async fn random_player(lobby: Proxy<Ticket<Player>>) {
+    let Ok(mut round) = lobby.connect().recv1().await else {
+        println!("Left behind...");
+        return;
+    };
+    while let Outcome::Draw(next_round) = round.send(random_move()).recv1().await {
+        round = next_round;
+    }
 }
 
 fn random_move() -> Move {
@@ -92,9 +97,20 @@ fn random_move() -> Move {
 
 #[tokio::main]
 async fn main() {
-    for _ in 0..10 {
+    let (games, mut ready) = mpsc::unbounded();
+    let lobby = Lobby::<Player, 3>::start(
+        move |players| games.unbounded_send(players).unwrap(),
+        |proxy| {
+            for _ in 0..31 {
+                proxy.clone(|proxy| drop(tokio::spawn(random_player(proxy))));
+            }
+        },
+    );
+    drop(tokio::spawn(lobby.run()));
+
+    while let Some([player1, player2, player3]) = ready.next().await {
         let winner = start_playing()
-            .send((random_player(), random_player(), random_player()))
+            .send((player1, player2, player3))
             .recv1()
             .await;
         println!("{:?}!\n", winner);
@@ -20,8 +20,8 @@
 //! - `Send<Result<A, B>>` is **A<sup>⊥</sup> & B<sup>⊥</sup>**
 
 use super::Session;
-use futures::channel::oneshot;
-use std::marker;
+use futures::{channel::oneshot, Future};
+use std::{marker, pin::Pin};
 
 /// Supplies a value of type `T`, then proceeds according to `S`. Its dual is [`Send<T, Dual<S>>`].
 ///
@@ -69,8 +69,11 @@ pub struct Send<T, S: Session = ()> {
 enum Exchange<T, S: Session> {
     Send((T, S)),
     Link(Recv<T, S>),
+    Lazy(LazyFuture<T, S>),
 }
 
+type LazyFuture<T, S> = Pin<Box<dyn Future<Output = (T, S)> + marker::Send + 'static>>;
+
 impl<T, S: Session> Session for Recv<T, S>
 where
     T: marker::Send + 'static,
@@ -127,9 +130,21 @@ where
             match self.rx.await.expect("sender dropped") {
                 Exchange::Send(x) => break x,
                 Exchange::Link(r) => self = r,
+                Exchange::Lazy(future) => break future.await,
             }
         }
     }
+
+    /// Creates a [`Recv`] supplied by a future. The future is only polled once the value is being
+    /// received, by whoever receives it.
+    pub(crate) fn lazy(future: impl Future<Output = (T, S)> + marker::Send + 'static) -> Self {
+        Self::fork_sync(|send| {
+            send.tx
+                .send(Exchange::Lazy(Box::pin(future)))
+                .ok()
+                .expect("receiver dropped")
+        })
+    }
 }
 
 impl<T> Recv<T, ()>
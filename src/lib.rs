@@ -856,6 +856,7 @@
 //! ```
 
 pub mod exchange;
+pub mod lobby;
 pub mod queue;
 pub mod runtimes;
 pub mod server;
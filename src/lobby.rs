@@ -0,0 +1,218 @@
+//! Group clients into sessions with a fixed number of participants. Start a [`Lobby`], let clients
+//! join it via a [`Proxy`], and receive the players of each game once enough of them are waiting.
+//!
+//! A client joining a lobby receives a [`Ticket`]: either its side of the game session, or
+//! [`LobbyCancelled`] if the lobby ends before a game could start.
+
+use super::{
+    exchange::{Recv, Send},
+    runtimes::Timer,
+    server::{Event, Proxy, Server},
+    Dual, Session,
+};
+use futures::{future, future::BoxFuture, FutureExt};
+use std::{
+    error::Error,
+    fmt, marker,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+/// Client's side of joining a [`Lobby`]. Resolves to the client's side of the game session once
+/// a game starts, or to [`LobbyCancelled`] if it never does.
+pub type Ticket<Player> = Recv<Result<Dual<Player>, LobbyCancelled>>;
+
+/// A client left behind in a [`Lobby`], without a game.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LobbyCancelled;
+
+impl fmt::Display for LobbyCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "lobby cancelled")
+    }
+}
+
+impl Error for LobbyCancelled {}
+
+/// Collects clients connecting through a [`Proxy`] until `N` of them are waiting, then hands their
+/// `Player` sessions to a game function. The `Player` session is the game's end-point of a single
+/// participant, and each client receives the dual of it.
+///
+/// Optionally, use [`with_timeout`](Self::with_timeout) to start smaller games when not enough
+/// clients come in time.
+///
+/// Must not be dropped. Use [`run`](Self::run) to collect the clients.
+#[must_use]
+pub struct Lobby<Player: Session, const N: usize> {
+    server: Server<Dual<Ticket<Player>>, (), ()>,
+    waiting: Vec<Dual<Ticket<Player>>>,
+    game: Box<dyn FnMut([Player; N]) + marker::Send>,
+    partial: Option<Partial<Player>>,
+    deadline: Option<BoxFuture<'static, ()>>,
+}
+
+struct Partial<Player> {
+    min: usize,
+    timeout: Duration,
+    timer: Box<dyn Timer>,
+    game: Box<dyn FnMut(Vec<Player>) + marker::Send>,
+}
+
+impl<Player: Session, const N: usize> Lobby<Player, N> {
+    /// Creates a new [`Lobby`] with the provided game function, and passes a [`Proxy`] to it to the
+    /// provided closure. The game function is called with each group of `N` players, in the order
+    /// they joined, and should start the game, for example, on a new task.
+    ///
+    /// Panics if `N` is zero.
+    pub fn start(
+        game: impl FnMut([Player; N]) + marker::Send + 'static,
+        f: impl FnOnce(Proxy<Ticket<Player>>),
+    ) -> Self {
+        assert!(N > 0, "empty games");
+        Self {
+            server: Server::start(f),
+            waiting: Vec::with_capacity(N),
+            game: Box::new(game),
+            partial: None,
+            deadline: None,
+        }
+    }
+
+    /// Starts a smaller game if fewer than `N` clients are waiting after the timeout, measured by the
+    /// provided [`Timer`] from the moment the first of them joined. If at least `min` clients are
+    /// waiting, they're passed to the provided game function. Otherwise, they're cancelled.
+    ///
+    /// Panics if `min` is zero or greater than `N`.
+    pub fn with_timeout(
+        mut self,
+        min: usize,
+        timeout: Duration,
+        timer: impl Timer,
+        game: impl FnMut(Vec<Player>) + marker::Send + 'static,
+    ) -> Self {
+        assert!(
+            (1..=N).contains(&min),
+            "minimum number of players out of range"
+        );
+        self.partial = Some(Partial {
+            min,
+            timeout,
+            timer: Box::new(timer),
+            game: Box::new(game),
+        });
+        self
+    }
+
+    /// Collects the clients and starts the games until no more [proxies](Proxy) of the lobby exist.
+    /// If a [timeout](Self::with_timeout) is running at that point, it's waited for first. The
+    /// clients still waiting afterwards are cancelled.
+    pub async fn run(mut self) {
+        future::poll_fn(|cx| self.poll_run(cx)).await
+    }
+
+    fn poll_run(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            if let Some(deadline) = &mut self.deadline {
+                if deadline.poll_unpin(cx).is_ready() {
+                    self.deadline = None;
+                    self.time_out();
+                }
+            }
+            match self.server.poll_event(cx) {
+                Poll::Ready(Some(Event::Connect { session })) => self.join(session),
+                Poll::Ready(Some(Event::Resume { .. } | Event::Expired { .. })) => {
+                    unreachable!("lobby connections are never suspended")
+                }
+                Poll::Ready(None) if self.deadline.is_some() => return Poll::Pending,
+                Poll::Ready(None) => {
+                    cancel(&mut self.waiting);
+                    return Poll::Ready(());
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn join(&mut self, client: Dual<Ticket<Player>>) {
+        self.waiting.push(client);
+        if self.waiting.len() == N {
+            self.deadline = None;
+            let Ok(players) = start(&mut self.waiting).try_into() else {
+                unreachable!()
+            };
+            (self.game)(players);
+        } else if self.waiting.len() == 1 {
+            if let Some(partial) = &self.partial {
+                self.deadline = Some(partial.timer.sleep(partial.timeout));
+            }
+        }
+    }
+
+    fn time_out(&mut self) {
+        if let Some(partial) = &mut self.partial {
+            if self.waiting.len() >= partial.min {
+                (partial.game)(start(&mut self.waiting));
+            } else {
+                cancel(&mut self.waiting);
+            }
+        }
+    }
+}
+
+fn start<Player: Session>(waiting: &mut Vec<Dual<Ticket<Player>>>) -> Vec<Player> {
+    waiting
+        .drain(..)
+        .map(|client| Player::fork_sync(|player| client.send1(Ok(player))))
+        .collect()
+}
+
+fn cancel<T: marker::Send + 'static>(waiting: &mut Vec<Send<Result<T, LobbyCancelled>>>) {
+    for client in waiting.drain(..) {
+        client.send1(Err(LobbyCancelled));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtimes::spawn::sleep;
+    use futures::executor::block_on;
+
+    fn play(clients: usize, min: usize) -> Vec<Option<usize>> {
+        let mut tickets = Vec::new();
+        let lobby = Lobby::<Send<usize>, 3>::start(
+            |players| players.into_iter().for_each(|player| player.send1(3)),
+            |proxy| {
+                for _ in 0..clients {
+                    proxy.clone(|proxy| tickets.push(proxy.connect()));
+                }
+            },
+        )
+        .with_timeout(min, Duration::from_millis(10), sleep, |players| {
+            let size = players.len();
+            players.into_iter().for_each(|player| player.send1(size));
+        });
+        block_on(lobby.run());
+        tickets
+            .into_iter()
+            .map(|ticket| {
+                block_on(async {
+                    match ticket.recv1().await {
+                        Ok(game) => Some(game.recv1().await),
+                        Err(LobbyCancelled) => None,
+                    }
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn leftover_clients_play_smaller_game_after_proxies_are_gone() {
+        assert_eq!(play(5, 2), [Some(3), Some(3), Some(3), Some(2), Some(2)]);
+    }
+
+    #[test]
+    fn too_few_leftover_clients_are_cancelled() {
+        assert_eq!(play(4, 2), [Some(3), Some(3), Some(3), None]);
+    }
+}
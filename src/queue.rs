@@ -19,18 +19,62 @@
 //! type Dequeue<T, S> = Recv<Queue<T, S>>;
 //! type Enqueue<T, S> = Send<Queue<T, Dual<S>>>;
 //! ```
+//!
+//! If the producer may outpace the consumer, [`BoundedEnqueue`] and [`BoundedDequeue`] provide
+//! the same structure with a limit on the number of items in transit. Pushing into a full
+//! [`BoundedEnqueue`] waits until the consumer pops some items.
+//!
+//! If each item needs an individual answer, [`AckEnqueue`] and [`AckDequeue`] pair every pushed
+//! item with a channel for its outcome.
+//!
+//! If the producer may need to abort, [`TryEnqueue`] and [`TryDequeue`] end either with the
+//! continuation, or with an error.
+//!
+//! [`PriorityEnqueue`] and [`PriorityDequeue`] don't follow the first-in, first-out order. Instead,
+//! popping produces the greatest of the items pushed so far.
+//!
+//! To move items between queues and the standard [`Stream`] and [`Iterator`] traits, use
+//! [`from_stream`](Dequeue::from_stream), [`from_iter`](Dequeue::from_iter),
+//! [`into_stream`](Dequeue::into_stream), [`send_all`](Enqueue::send_all) and
+//! [`extend`](Enqueue::extend). The combinators, such as [`map`](Dequeue::map) and
+//! [`chunks`](Dequeue::chunks), transform a [`Dequeue`] into another one. Neither these nor
+//! [`from_stream`](Dequeue::from_stream) and [`from_iter`](Dequeue::from_iter) fork a producer:
+//! the resulting queue is lazy, and the source is only polled by whoever pops the items, as they
+//! are being popped. Only [`collect`](Iterator::collect) into a [`Dequeue`] pushes all the items
+//! right away.
+//!
+//! Use [`merge`] or [`merge1`] to combine items from multiple queues into a single one. In the other
+//! direction, [`Broadcaster`] pushes items into many queues, and [`tee`](Dequeue::tee) duplicates
+//! a single queue.
 
 use super::{
     exchange::{Recv, Send},
     Session,
 };
-use futures::{Future, FutureExt, Stream};
+use futures::{future, stream, Future, FutureExt, Sink, Stream, StreamExt};
 use std::{
+    collections::VecDeque,
+    convert::Infallible,
     marker,
     pin::Pin,
     task::{Context, Poll},
 };
 
+mod ack;
+mod bounded;
+mod broadcast;
+mod concurrent;
+mod fallible;
+mod group;
+mod priority;
+mod timed;
+
+pub use ack::{AckDequeue, AckEnqueue, AckQueue, AckReplies};
+pub use bounded::{BoundedDequeue, BoundedEnqueue, BoundedQueue};
+pub use broadcast::Broadcaster;
+pub use fallible::{TryDequeue, TryDequeueStream, TryDequeueStream1, TryEnqueue, TryQueue};
+pub use priority::{PriorityDequeue, PriorityEnqueue, PriorityQueue};
+
 /// Produces an arbitrary number of values of type `T`, then proceeds according to `S`. Its dual
 /// is [`Enqueue<T, Dual<S>>`].
 ///
@@ -38,6 +82,9 @@ use std::{
 /// or the continuation `S` if all the values have already been popped. Use [`fold`](Self::fold) or
 /// [`for_each`](Self::for_each) to process the values more ergonomically. If the continuation is
 /// `()` (the empty session), use [`fold1`](Self::fold1) or [`for_each1`](Self::for_each1).
+///
+/// Combinators such as [`map`](Self::map), [`filter`](Self::filter), or [`take`](Self::take)
+/// produce a new [`Dequeue`] while keeping the continuation `S`.
 #[must_use]
 pub struct Dequeue<T, S: Session = ()> {
     deq: Recv<Queue<T, S>>,
@@ -109,20 +156,17 @@ where
     /// asynchronous function and the initial value. Returns the final result along with the
     /// continuation `S`.
     #[must_use]
-    pub async fn fold<A, F>(mut self, init: A, mut f: impl FnMut(A, T) -> F) -> (A, S)
+    pub async fn fold<A, F>(self, init: A, f: impl FnMut(A, T) -> F) -> (A, S)
     where
         F: Future<Output = A>,
     {
-        let mut accum = init;
-        loop {
-            match self.pop().await {
-                Queue::Item(item, rest) => {
-                    accum = f(accum, item).await;
-                    self = rest;
-                }
-                Queue::Closed(session) => return (accum, session),
-            }
-        }
+        let pop = |deq: Self| {
+            deq.pop().map(|queue| match queue {
+                Queue::Item(item, rest) => Ok((item, rest)),
+                Queue::Closed(session) => Err(session),
+            })
+        };
+        fold_with(self, init, pop, f).await
     }
 
     /// Runs the provided asynchronous function for each item from the queue. Next iteration
@@ -145,6 +189,171 @@ where
     }
 }
 
+impl<T, S: Session> Dequeue<T, S>
+where
+    T: marker::Send + 'static,
+{
+    /// Transforms each item from the queue by the provided function. The continuation `S`
+    /// is preserved. The items are transformed as they are being popped.
+    pub fn map<U, F>(self, mut f: F) -> Dequeue<U, S>
+    where
+        U: marker::Send + 'static,
+        F: FnMut(T) -> U + marker::Send + 'static,
+    {
+        Dequeue {
+            deq: Recv::lazy(async move {
+                match self.pop().await {
+                    Queue::Item(item, rest) => (Queue::Item(f(item), rest.map(f)), ()),
+                    Queue::Closed(session) => (Queue::Closed(session), ()),
+                }
+            }),
+        }
+    }
+
+    /// Only keeps the items from the queue satisfying the provided predicate. The continuation
+    /// `S` is preserved.
+    pub fn filter<F>(self, mut f: F) -> Self
+    where
+        F: FnMut(&T) -> bool + marker::Send + 'static,
+    {
+        Self {
+            deq: Recv::lazy(async move {
+                let mut deq = self;
+                loop {
+                    match deq.pop().await {
+                        Queue::Item(item, rest) if f(&item) => {
+                            break (Queue::Item(item, rest.filter(f)), ())
+                        }
+                        Queue::Item(_, rest) => deq = rest,
+                        Queue::Closed(session) => break (Queue::Closed(session), ()),
+                    }
+                }
+            }),
+        }
+    }
+
+    /// Transforms the items from the queue by the provided function, only keeping those for
+    /// which it returns [`Some`]. The continuation `S` is preserved.
+    pub fn filter_map<U, F>(self, mut f: F) -> Dequeue<U, S>
+    where
+        U: marker::Send + 'static,
+        F: FnMut(T) -> Option<U> + marker::Send + 'static,
+    {
+        Dequeue {
+            deq: Recv::lazy(async move {
+                let mut deq = self;
+                loop {
+                    match deq.pop().await {
+                        Queue::Item(item, rest) => match f(item) {
+                            Some(item) => break (Queue::Item(item, rest.filter_map(f)), ()),
+                            None => deq = rest,
+                        },
+                        Queue::Closed(session) => break (Queue::Closed(session), ()),
+                    }
+                }
+            }),
+        }
+    }
+
+    /// Produces at most `n` first items from the queue. Its continuation is a [`Dequeue`] with
+    /// the remaining items, which is empty if the queue has closed before producing `n` items.
+    pub fn take(self, n: usize) -> Dequeue<T, Dequeue<T, S>> {
+        Dequeue {
+            deq: Recv::lazy(async move {
+                if n == 0 {
+                    return (Queue::Closed(self), ());
+                }
+                match self.pop().await {
+                    Queue::Item(item, rest) => (Queue::Item(item, rest.take(n - 1)), ()),
+                    Queue::Closed(session) => (
+                        Queue::Closed(Dequeue::fork_sync(|enq| session.link(enq.close()))),
+                        (),
+                    ),
+                }
+            }),
+        }
+    }
+
+    /// Groups the items from the queue into vectors of `n` items. The last vector may be shorter
+    /// if the number of items is not divisible by `n`. The continuation `S` is preserved.
+    ///
+    /// Panics if `n` is zero.
+    pub fn chunks(self, n: usize) -> Dequeue<Vec<T>, S> {
+        assert!(n != 0, "chunk size must be non-zero");
+        Dequeue {
+            deq: Recv::lazy(async move {
+                let mut chunk = Vec::new();
+                let mut deq = self;
+                loop {
+                    match deq.pop().await {
+                        Queue::Item(item, rest) => {
+                            chunk.push(item);
+                            if chunk.len() == n {
+                                break (Queue::Item(chunk, rest.chunks(n)), ());
+                            }
+                            deq = rest;
+                        }
+                        Queue::Closed(session) if chunk.is_empty() => {
+                            break (Queue::Closed(session), ())
+                        }
+                        Queue::Closed(session) => {
+                            let rest = Dequeue::fork_sync(|enq| session.link(enq.close()));
+                            break (Queue::Item(chunk, rest), ());
+                        }
+                    }
+                }
+            }),
+        }
+    }
+
+    /// Pairs each item from the queue with its index, starting from zero. The continuation `S`
+    /// is preserved.
+    pub fn enumerate(self) -> Dequeue<(usize, T), S> {
+        let mut index = 0;
+        self.map(move |item| {
+            index += 1;
+            (index - 1, item)
+        })
+    }
+
+    /// Creates a [`Dequeue`] producing all items from a [`Stream`](futures::Stream), and closing
+    /// with the provided continuation once the stream ends.
+    ///
+    /// No producer is forked. The stream is polled as the items are being popped, by whoever pops
+    /// them, so a queue that's never popped never polls the stream.
+    pub fn from_stream(stream: impl Stream<Item = T> + marker::Send + 'static, session: S) -> Self {
+        Self::from_pinned_stream(Box::pin(stream), session)
+    }
+
+    /// Creates a [`Dequeue`] producing all items from an iterator, and closing with the provided
+    /// continuation once the iterator ends. Like [`from_stream`](Self::from_stream), the iterator
+    /// is advanced as the items are being popped, so it may be infinite.
+    pub fn from_iter<I>(iter: I, session: S) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: marker::Send + 'static,
+    {
+        Self::from_stream(stream::iter(iter), session)
+    }
+
+    fn from_pinned_stream<St>(mut stream: Pin<Box<St>>, session: S) -> Self
+    where
+        St: Stream<Item = T> + marker::Send + 'static + ?Sized,
+    {
+        Self {
+            deq: Recv::lazy(async move {
+                match stream.next().await {
+                    Some(item) => (
+                        Queue::Item(item, Self::from_pinned_stream(stream, session)),
+                        (),
+                    ),
+                    None => (Queue::Closed(session), ()),
+                }
+            }),
+        }
+    }
+}
+
 impl<T> Dequeue<T, ()>
 where
     T: marker::Send + 'static,
@@ -174,6 +383,35 @@ where
             future: Box::pin(self.pop()),
         }
     }
+
+    /// Creates a [`Dequeue`] producing all items from a [`Stream`](futures::Stream), and closing
+    /// once the stream ends. Like [`from_stream`](Self::from_stream), the stream is polled as the
+    /// items are being popped.
+    pub fn from_stream1(stream: impl Stream<Item = T> + marker::Send + 'static) -> Self {
+        Self::from_stream(stream, ())
+    }
+
+    /// Creates a [`Dequeue`] producing all items from an iterator, and closing once the iterator
+    /// ends. Like [`from_iter`](Self::from_iter), the iterator is advanced as the items are being
+    /// popped.
+    pub fn from_iter1<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: marker::Send + 'static,
+    {
+        Self::from_iter(iter, ())
+    }
+}
+
+impl<T> FromIterator<T> for Dequeue<T, ()>
+where
+    T: marker::Send + 'static,
+{
+    /// Creates a [`Dequeue`] producing all items from an iterator, then closing. All the items
+    /// are pushed right away, use [`Dequeue::from_iter1`] to advance the iterator lazily.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::fork_sync(|enq| enq.extend(iter).close1())
+    }
 }
 
 impl<T, S: Session> Enqueue<T, S>
@@ -192,6 +430,28 @@ where
     pub fn push(self, item: T) -> Self {
         Self::fork_sync(|dual| self.enq.send1(Queue::Item(item, dual)))
     }
+
+    /// Pushes all values from an iterator into the queue, in order.
+    pub fn extend(self, iter: impl IntoIterator<Item = T>) -> Self {
+        iter.into_iter().fold(self, Self::push)
+    }
+
+    /// Pushes all values from a [`Stream`](futures::Stream) into the queue, in order. Returns
+    /// the [`Enqueue`] for further use once the stream ends. The stream is polled by the caller,
+    /// pushing never waits for the consumer.
+    pub async fn send_all(self, stream: impl Stream<Item = T>) -> Self {
+        stream
+            .fold(self, |enq, item| future::ready(enq.push(item)))
+            .await
+    }
+
+    /// Turns an [`Enqueue`] into a standard [`Sink`](futures::Sink) of `T`. Closing the sink closes
+    /// the queue, use [`EnqueueSink::finish`] to obtain the continuation `S` afterwards.
+    pub fn into_sink(self) -> EnqueueSink<T, S> {
+        EnqueueSink {
+            state: SinkState::Open(self),
+        }
+    }
 }
 
 impl<T> Enqueue<T, ()>
@@ -204,6 +464,108 @@ where
     }
 }
 
+/// Merges multiple queues into one, interleaving their items fairly. The merged queue is closed
+/// once all the input queues are closed. Its continuation supplies the continuations of all
+/// the input queues, in the original order.
+pub fn merge<T, S: Session>(
+    queues: impl IntoIterator<Item = Dequeue<T, S>>,
+) -> Dequeue<T, Recv<Vec<S>>>
+where
+    T: marker::Send + 'static,
+{
+    merged(start_merge(queues), |sessions| {
+        Recv::fork_sync(|send| send.send1(sessions))
+    })
+}
+
+/// Merges multiple queues without a continuation into one, interleaving their items fairly.
+/// The merged queue is closed once all the input queues are closed.
+pub fn merge1<T>(queues: impl IntoIterator<Item = Dequeue<T, ()>>) -> Dequeue<T, ()>
+where
+    T: marker::Send + 'static,
+{
+    merged(start_merge(queues), |_| ())
+}
+
+/// The loop behind `fold` of all the queue flavors. The `pop` function produces either the next
+/// item along with the rest of the queue, or whatever the queue ends with.
+async fn fold_with<Q, T, End, A, P, F>(
+    mut queue: Q,
+    init: A,
+    mut pop: impl FnMut(Q) -> P,
+    mut f: impl FnMut(A, T) -> F,
+) -> (A, End)
+where
+    P: Future<Output = Result<(T, Q), End>>,
+    F: Future<Output = A>,
+{
+    let mut accum = init;
+    loop {
+        match pop(queue).await {
+            Ok((item, rest)) => {
+                accum = f(accum, item).await;
+                queue = rest;
+            }
+            Err(end) => return (accum, end),
+        }
+    }
+}
+
+type PopFuture<T, S> = Pin<Box<dyn Future<Output = Queue<T, S>> + marker::Send + 'static>>;
+
+struct Merge<T, S: Session> {
+    pending: VecDeque<(usize, PopFuture<T, S>)>,
+    closed: Vec<Option<S>>,
+}
+
+fn start_merge<T, S: Session>(queues: impl IntoIterator<Item = Dequeue<T, S>>) -> Merge<T, S>
+where
+    T: marker::Send + 'static,
+{
+    let pending = queues
+        .into_iter()
+        .enumerate()
+        .map(|(index, deq)| (index, Box::pin(deq.pop()) as PopFuture<T, S>))
+        .collect::<VecDeque<_>>();
+    let closed = pending.iter().map(|_| None).collect();
+    Merge { pending, closed }
+}
+
+fn merged<T, S: Session, C: Session>(
+    mut merge: Merge<T, S>,
+    finish: fn(Vec<S>) -> C,
+) -> Dequeue<T, C>
+where
+    T: marker::Send + 'static,
+{
+    Dequeue {
+        deq: Recv::lazy(async move {
+            while !merge.pending.is_empty() {
+                let (index, queue) = future::poll_fn(|cx| {
+                    for i in 0..merge.pending.len() {
+                        if let Poll::Ready(queue) = merge.pending[i].1.poll_unpin(cx) {
+                            let (index, _) = merge.pending.remove(i).expect("missing queue");
+                            return Poll::Ready((index, queue));
+                        }
+                    }
+                    Poll::Pending
+                })
+                .await;
+                match queue {
+                    Queue::Item(item, rest) => {
+                        merge.pending.push_back((index, Box::pin(rest.pop())));
+                        return (Queue::Item(item, merged(merge, finish)), ());
+                    }
+                    Queue::Closed(session) => merge.closed[index] = Some(session),
+                }
+            }
+            let sessions = merge.closed.into_iter();
+            let sessions = sessions.map(|s| s.expect("missing continuation"));
+            (Queue::Closed(finish(sessions.collect())), ())
+        }),
+    }
+}
+
 /// A [`Stream`](futures::Stream) of [`Next<T, S>`] producing all items from the queue before
 /// producing its final continuation.
 pub struct DequeueStream<T, S: Session> {
@@ -257,3 +619,214 @@ where
         }
     }
 }
+
+/// A [`Sink`](futures::Sink) pushing all items into an [`Enqueue`]. Closing the sink closes the
+/// queue and keeps the continuation `S` until it's taken by [`finish`](Self::finish).
+///
+/// If `S` is `()` (the empty session), closing the sink is all that's needed.
+#[must_use]
+pub struct EnqueueSink<T, S: Session> {
+    state: SinkState<T, S>,
+}
+
+enum SinkState<T, S: Session> {
+    Open(Enqueue<T, S>),
+    Closed(S),
+    Finished,
+}
+
+impl<T, S: Session> EnqueueSink<T, S>
+where
+    T: marker::Send + 'static,
+{
+    /// Closes the queue, unless the sink has already been closed, and returns the continuation `S`.
+    #[must_use]
+    pub fn finish(mut self) -> S {
+        match std::mem::replace(&mut self.state, SinkState::Finished) {
+            SinkState::Open(enq) => enq.close(),
+            SinkState::Closed(session) => session,
+            SinkState::Finished => unreachable!(),
+        }
+    }
+}
+
+impl<T, S: Session> Unpin for EnqueueSink<T, S> {}
+
+impl<T, S: Session> Sink<T> for EnqueueSink<T, S>
+where
+    T: marker::Send + 'static,
+{
+    type Error = Infallible;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        self.state = match std::mem::replace(&mut self.state, SinkState::Finished) {
+            SinkState::Open(enq) => SinkState::Open(enq.push(item)),
+            _ => panic!("sink closed"),
+        };
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.state = match std::mem::replace(&mut self.state, SinkState::Finished) {
+            SinkState::Open(enq) => SinkState::Closed(enq.close()),
+            state => state,
+        };
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use futures::{executor::block_on, stream, SinkExt};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    /// Collects items with `fold`.
+    pub(crate) async fn push<T>(mut all: Vec<T>, item: T) -> Vec<T> {
+        all.push(item);
+        all
+    }
+
+    /// Creates a session along with its dual, the consumer along with its producer for queues.
+    pub(crate) fn endpoints<S: Session>() -> (S, S::Dual) {
+        let mut dual = None;
+        let session = S::fork_sync(|d| dual = Some(d));
+        (session, dual.expect("missing dual"))
+    }
+
+    #[test]
+    fn sink_pushes_items_and_keeps_the_continuation() {
+        let mut sink = None;
+        let deq = Dequeue::<i32, Recv<bool>>::fork_sync(|enq| sink = Some(enq.into_sink()));
+        let mut sink = sink.expect("missing sink");
+        block_on(sink.send_all(&mut stream::iter([1, 2, 3]).map(Ok))).unwrap();
+        block_on(sink.close()).unwrap();
+        sink.finish().send1(true);
+
+        let (items, session) = block_on(deq.fold(Vec::new(), push));
+        assert_eq!(items, [1, 2, 3]);
+        assert!(block_on(session.recv1()));
+    }
+
+    #[test]
+    fn from_stream_polls_as_items_are_popped_and_keeps_the_continuation() {
+        let polled = Arc::new(AtomicUsize::new(0));
+        let stream = stream::iter([1, 2, 3]).inspect({
+            let polled = Arc::clone(&polled);
+            move |_| {
+                polled.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        let mut session = None;
+        let deq = Dequeue::from_stream(stream, Recv::<bool>::fork_sync(|s| session = Some(s)));
+        assert_eq!(polled.load(Ordering::SeqCst), 0);
+
+        let Queue::Item(1, deq) = block_on(deq.pop()) else {
+            panic!("expected the first item");
+        };
+        assert_eq!(polled.load(Ordering::SeqCst), 1);
+        session.expect("missing session").send1(true);
+        let (items, session) = block_on(deq.fold(Vec::new(), push));
+        assert_eq!(items, [2, 3]);
+        assert!(block_on(session.recv1()));
+    }
+
+    #[test]
+    fn from_iter_advances_lazily_and_keeps_the_continuation() {
+        let mut session = None;
+        let deq = Dequeue::from_iter(0.., Recv::<bool>::fork_sync(|s| session = Some(s)));
+        let (taken, _) = block_on(deq.take(3).fold(Vec::new(), push));
+        assert_eq!(taken, [0, 1, 2]);
+
+        let deq = Dequeue::from_iter([1, 2], Recv::<bool>::fork_sync(|s| session = Some(s)));
+        session.expect("missing session").send1(true);
+        let (items, session) = block_on(deq.fold(Vec::new(), push));
+        assert_eq!(items, [1, 2]);
+        assert!(block_on(session.recv1()));
+    }
+
+    #[test]
+    fn send_all_and_extend_push_items_in_order() {
+        let deq = Dequeue::<i32>::fork_sync(|enq| {
+            let enq = block_on(enq.send_all(stream::iter([1, 2])));
+            enq.extend([3, 4]).push(5).close1()
+        });
+        assert_eq!(block_on(deq.fold1(Vec::new(), push)), [1, 2, 3, 4, 5]);
+        assert_eq!(
+            block_on(Dequeue::from_stream1(stream::iter(1..=3)).fold1(Vec::new(), push)),
+            [1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn chained_combinators_keep_the_continuation() {
+        let deq = Dequeue::<i32, Recv<bool>>::fork_sync(|enq| {
+            enq.extend(1..=10).close().send1(true);
+        });
+        let deq = deq
+            .map(|item| item * 3)
+            .filter(|item| item % 2 == 0)
+            .filter_map(|item| (item != 12).then_some(item / 2))
+            .enumerate();
+        let (items, session) = block_on(deq.fold(Vec::new(), push));
+        assert_eq!(items, [(0, 3), (1, 9), (2, 12), (3, 15)]);
+        assert!(block_on(session.recv1()));
+    }
+
+    #[test]
+    fn take_leaves_the_rest_in_its_continuation() {
+        let (taken, rest) = block_on(Dequeue::from_iter1(1..=5).take(2).fold(Vec::new(), push));
+        assert_eq!(taken, [1, 2]);
+        assert_eq!(block_on(rest.fold1(Vec::new(), push)), [3, 4, 5]);
+
+        let (taken, rest) = block_on(Dequeue::from_iter1(1..=2).take(5).fold(Vec::new(), push));
+        assert_eq!(taken, [1, 2]);
+        assert!(matches!(block_on(rest.pop()), Queue::Closed(())));
+    }
+
+    #[test]
+    fn chunks_keep_a_shorter_last_chunk() {
+        let chunks = Dequeue::from_iter1(1..=5).chunks(2);
+        assert_eq!(
+            block_on(chunks.fold1(Vec::new(), push)),
+            [vec![1, 2], vec![3, 4], vec![5]]
+        );
+
+        let chunks = Dequeue::from_iter1(1..=3).chunks(usize::MAX);
+        assert_eq!(block_on(chunks.fold1(Vec::new(), push)), [vec![1, 2, 3]]);
+    }
+
+    #[test]
+    fn merge_interleaves_ready_queues() {
+        let queues = [Dequeue::from_iter1(1..=3), Dequeue::from_iter1(10..=11)];
+        assert_eq!(
+            block_on(merge1(queues).fold1(Vec::new(), push)),
+            [1, 10, 2, 11, 3]
+        );
+    }
+
+    #[test]
+    fn merge_keeps_the_order_of_continuations() {
+        let queues = (0..3).map(|index| {
+            Dequeue::<i32, Recv<i32>>::fork_sync(|enq| enq.push(index).close().send1(index * 10))
+        });
+        let (items, sessions) = block_on(merge(queues).fold(Vec::new(), push));
+        assert_eq!(items, [0, 1, 2]);
+        let sessions = block_on(sessions.recv1()).into_iter().map(Recv::recv1);
+        assert_eq!(block_on(future::join_all(sessions)), [0, 10, 20]);
+    }
+}
@@ -0,0 +1,262 @@
+use super::{Dequeue, Enqueue, Queue};
+use crate::{
+    exchange::{Recv, Send},
+    Session,
+};
+use futures::{future::BoxFuture, stream::FuturesOrdered, Future, FutureExt, Stream, StreamExt};
+use std::{
+    marker,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Produces an arbitrary number of values of type `T`, each expecting an outcome of type `R`, then
+/// proceeds according to `S`. Its dual is [`AckEnqueue<T, R, Dual<S>>`].
+///
+/// Use [`pop`](Self::pop) to obtain the next item along with a [`Send<R>`] for its outcome. The
+/// outcomes may be supplied in any order. Use [`serve`](Self::serve) or
+/// [`serve_concurrent`](Self::serve_concurrent) to answer all the items with a function.
+#[must_use]
+pub struct AckDequeue<T, R, S: Session = ()> {
+    deq: Dequeue<(T, Send<R>), S>,
+}
+
+/// Accepts an arbitrary number of values of type `T`, each producing an outcome of type `R`, then
+/// proceeds according to `S`. Its dual is [`AckDequeue<T, R, Dual<S>>`].
+///
+/// Use [`push`](Self::push) to send a value over the queue and obtain a [`Recv<R>`] for its outcome.
+/// Pushing does not wait for the outcomes, so any number of items may be outstanding. To receive
+/// the outcomes in the order of pushing, collect them in [`AckReplies`].
+#[must_use]
+pub struct AckEnqueue<T, R, S: Session = ()> {
+    enq: Enqueue<(T, Send<R>), S>,
+}
+
+/// The result of [`AckDequeue::pop`].
+pub enum AckQueue<T, R, S: Session = ()> {
+    Item(T, Send<R>, AckDequeue<T, R, S>),
+    Closed(S),
+}
+
+impl<T, R, S: Session> Session for AckDequeue<T, R, S>
+where
+    T: marker::Send + 'static,
+    R: marker::Send + 'static,
+{
+    type Dual = AckEnqueue<T, R, S::Dual>;
+
+    fn fork_sync(f: impl FnOnce(Self::Dual)) -> Self {
+        Self {
+            deq: Dequeue::fork_sync(|enq| f(AckEnqueue { enq })),
+        }
+    }
+
+    fn link(self, dual: Self::Dual) {
+        self.deq.link(dual.enq)
+    }
+}
+
+impl<T, R, S: Session> Session for AckEnqueue<T, R, S>
+where
+    T: marker::Send + 'static,
+    R: marker::Send + 'static,
+{
+    type Dual = AckDequeue<T, R, S::Dual>;
+
+    fn fork_sync(f: impl FnOnce(Self::Dual)) -> Self {
+        Self {
+            enq: Enqueue::fork_sync(|deq| f(AckDequeue { deq })),
+        }
+    }
+
+    fn link(self, dual: Self::Dual) {
+        self.enq.link(dual.deq)
+    }
+}
+
+impl<T, R, S: Session> AckDequeue<T, R, S>
+where
+    T: marker::Send + 'static,
+    R: marker::Send + 'static,
+{
+    /// Waits to receive the next item of type `T` pushed in the queue along with a [`Send<R>`]
+    /// for its outcome, or the continuation `S` if the queue has been closed.
+    #[must_use]
+    pub async fn pop(self) -> AckQueue<T, R, S> {
+        match self.deq.pop().await {
+            Queue::Item((item, reply), deq) => AckQueue::Item(item, reply, Self { deq }),
+            Queue::Closed(session) => AckQueue::Closed(session),
+        }
+    }
+
+    /// Answers each item from the queue with the outcome of the provided asynchronous function.
+    /// Next item is not processed before the previous one is answered. Returns the continuation `S`.
+    #[must_use]
+    pub async fn serve<F>(self, mut f: impl FnMut(T) -> F) -> S
+    where
+        F: Future<Output = R>,
+    {
+        self.deq
+            .for_each(|(item, reply)| f(item).map(|outcome| reply.send1(outcome)))
+            .await
+    }
+
+    /// Answers each item from the queue with the outcome of the provided asynchronous function,
+    /// with up to `limit` of them processed concurrently. A `limit` of zero means no limit. The
+    /// items may be answered out of order. Returns the continuation `S` once all the items are
+    /// answered.
+    #[must_use]
+    pub async fn serve_concurrent<F>(self, limit: usize, mut f: impl FnMut(T) -> F) -> S
+    where
+        F: Future<Output = R>,
+    {
+        self.deq
+            .for_each_concurrent(limit, |(item, reply)| {
+                f(item).map(|outcome| reply.send1(outcome))
+            })
+            .await
+    }
+
+    /// Turns the [`AckDequeue`] into a plain [`Dequeue`] of items paired with their outcome
+    /// channels.
+    pub fn into_dequeue(self) -> Dequeue<(T, Send<R>), S> {
+        self.deq
+    }
+}
+
+impl<T, R, S: Session> AckEnqueue<T, R, S>
+where
+    T: marker::Send + 'static,
+    R: marker::Send + 'static,
+{
+    /// Closes the queue, signaling to the other side that no more items will be pushed. Returns
+    /// the continuation `S`.
+    #[must_use]
+    pub fn close(self) -> S {
+        self.enq.close()
+    }
+
+    /// Pushes a value of type `T` into the queue. Returns the [`AckEnqueue`] for further use, along
+    /// with a [`Recv<R>`] for the outcome of the pushed item.
+    pub fn push(self, item: T) -> (Self, Recv<R>) {
+        let mut enq = None;
+        let reply = Recv::fork_sync(|send| enq = Some(self.enq.push((item, send))));
+        (Self { enq: enq.unwrap() }, reply)
+    }
+
+    /// Pushes a value of type `T` into the queue, and adds the [`Recv<R>`] for its outcome to
+    /// `replies`.
+    pub fn push_into(self, item: T, replies: &mut AckReplies<R>) -> Self {
+        let (enq, reply) = self.push(item);
+        replies.push(reply);
+        enq
+    }
+
+    /// Turns the [`AckEnqueue`] into a plain [`Enqueue`] of items paired with their outcome
+    /// channels.
+    pub fn into_enqueue(self) -> Enqueue<(T, Send<R>), S> {
+        self.enq
+    }
+}
+
+impl<T, R> AckEnqueue<T, R, ()>
+where
+    T: marker::Send + 'static,
+    R: marker::Send + 'static,
+{
+    /// Closes the queue, signaling to the other side that no more items will be pushed.
+    pub fn close1(self) {
+        self.close()
+    }
+}
+
+/// A [`Stream`](futures::Stream) of outstanding outcomes from an [`AckEnqueue`], producing them
+/// in the order of pushing, regardless of the order in which they are supplied.
+///
+/// All the outcomes should be received before dropping.
+#[must_use]
+pub struct AckReplies<R> {
+    replies: FuturesOrdered<BoxFuture<'static, R>>,
+}
+
+impl<R> Default for AckReplies<R> {
+    fn default() -> Self {
+        Self {
+            replies: FuturesOrdered::new(),
+        }
+    }
+}
+
+impl<R> AckReplies<R>
+where
+    R: marker::Send + 'static,
+{
+    /// Creates an empty [`AckReplies`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an outcome to wait for after all the previously added ones.
+    pub fn push(&mut self, reply: Recv<R>) {
+        self.replies.push_back(reply.recv1().boxed())
+    }
+
+    /// Returns the number of outstanding outcomes.
+    pub fn len(&self) -> usize {
+        self.replies.len()
+    }
+
+    /// Returns whether there are no outstanding outcomes.
+    pub fn is_empty(&self) -> bool {
+        self.replies.is_empty()
+    }
+}
+
+impl<R> Stream for AckReplies<R>
+where
+    R: marker::Send + 'static,
+{
+    type Item = R;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.replies.poll_next_unpin(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::tests::endpoints;
+    use futures::executor::block_on;
+
+    #[test]
+    fn replies_come_in_the_order_of_pushing() {
+        let mut replies = AckReplies::new();
+        let (deq, enq) = endpoints::<AckDequeue<i32, i32>>();
+        let enq = enq.push_into(1, &mut replies).push_into(2, &mut replies);
+        enq.close1();
+
+        let AckQueue::Item(1, first, deq) = block_on(deq.pop()) else {
+            panic!("expected the first item");
+        };
+        let AckQueue::Item(2, second, deq) = block_on(deq.pop()) else {
+            panic!("expected the second item");
+        };
+        second.send1(20);
+        first.send1(10);
+        assert!(matches!(block_on(deq.pop()), AckQueue::Closed(())));
+        assert_eq!(block_on(replies.collect::<Vec<_>>()), [10, 20]);
+    }
+
+    #[test]
+    fn serve_answers_every_item() {
+        let mut replies = AckReplies::new();
+        let deq = AckDequeue::<i32, i32>::fork_sync(|enq| {
+            (1..=3)
+                .fold(enq, |enq, item| enq.push_into(item, &mut replies))
+                .close1()
+        });
+        block_on(deq.serve_concurrent(2, |item| async move { item * 10 }));
+        assert_eq!(block_on(replies.collect::<Vec<_>>()), [10, 20, 30]);
+    }
+}
@@ -0,0 +1,244 @@
+use super::fold_with;
+use crate::{
+    exchange::{Recv, Send},
+    Session,
+};
+use futures::{channel::oneshot, Future, FutureExt};
+use std::{collections::VecDeque, marker};
+
+/// Produces an arbitrary number of values of type `T`, then proceeds according to `S`. At most `N`
+/// values are in transit at any time. Its dual is [`BoundedEnqueue<T, N, Dual<S>>`].
+///
+/// Use [`pop`](Self::pop) to obtain the next item of type `T` from the queue (if there is any),
+/// or the continuation `S` if all the values have already been popped. Popping an item returns
+/// its credit to the producer.
+#[must_use]
+pub struct BoundedDequeue<T, const N: usize, S: Session = ()> {
+    deq: Recv<Bounded<T, N, S>>,
+}
+
+/// Accepts an arbitrary number of values of type `T`, then proceeds according to `S`. At most `N`
+/// values are in transit at any time. Its dual is [`BoundedDequeue<T, N, Dual<S>>`].
+///
+/// Unlike [`Enqueue::push`](super::Enqueue::push), pushing is asynchronous. Every pushed item takes
+/// up a credit, which is returned once the item is popped on the other side. If all `N` credits
+/// are taken, pushing waits until the consumer catches up.
+///
+/// Use [`push`](Self::push) to send a value over the queue, waiting for a credit if necessary. To
+/// stop sending values and obtain the continuation `S`, use [`close`](Self::close), or
+/// [`close1`](Self::close1) if `S` is `()` (the empty session).
+///
+/// [Linking](Session::link) doesn't carry the outstanding credits over. Items still in transit from
+/// before the link no longer count against `N`, so up to `2 * N` items can be in transit until the
+/// consumer pops them.
+#[must_use]
+pub struct BoundedEnqueue<T, const N: usize, S: Session = ()> {
+    enq: Send<Bounded<T, N, S::Dual>>,
+    credits: VecDeque<oneshot::Receiver<()>>,
+}
+
+/// The result of [`BoundedDequeue::pop`].
+pub enum BoundedQueue<T, const N: usize, S: Session = ()> {
+    Item(T, BoundedDequeue<T, N, S>),
+    Closed(S),
+}
+
+enum Bounded<T, const N: usize, S: Session> {
+    Item(T, oneshot::Sender<()>, BoundedDequeue<T, N, S>),
+    Closed(S),
+}
+
+impl<T, const N: usize, S: Session> Session for BoundedDequeue<T, N, S>
+where
+    T: marker::Send + 'static,
+{
+    type Dual = BoundedEnqueue<T, N, S::Dual>;
+
+    fn fork_sync(f: impl FnOnce(Self::Dual)) -> Self {
+        Self {
+            deq: Recv::fork_sync(|send| {
+                f(BoundedEnqueue {
+                    enq: send,
+                    credits: VecDeque::new(),
+                })
+            }),
+        }
+    }
+
+    fn link(self, dual: Self::Dual) {
+        self.deq.link(dual.enq)
+    }
+}
+
+impl<T, const N: usize, S: Session> Session for BoundedEnqueue<T, N, S>
+where
+    T: marker::Send + 'static,
+{
+    type Dual = BoundedDequeue<T, N, S::Dual>;
+
+    fn fork_sync(f: impl FnOnce(Self::Dual)) -> Self {
+        Self {
+            enq: Send::fork_sync(|recv| f(BoundedDequeue { deq: recv })),
+            credits: VecDeque::new(),
+        }
+    }
+
+    fn link(self, dual: Self::Dual) {
+        self.enq.link(dual.deq)
+    }
+}
+
+impl<T, const N: usize, S: Session> BoundedDequeue<T, N, S>
+where
+    T: marker::Send + 'static,
+{
+    /// Waits to receive the next item of type `T` pushed in the queue, or the continuation `S`
+    /// if the queue has been closed. Receiving an item returns its credit to the producer.
+    #[must_use]
+    pub async fn pop(self) -> BoundedQueue<T, N, S> {
+        match self.deq.recv1().await {
+            Bounded::Item(item, credit, rest) => {
+                let _ = credit.send(());
+                BoundedQueue::Item(item, rest)
+            }
+            Bounded::Closed(session) => BoundedQueue::Closed(session),
+        }
+    }
+
+    /// Accumulates all the items from the queue into a final result according to the provided
+    /// asynchronous function and the initial value. Returns the final result along with the
+    /// continuation `S`.
+    #[must_use]
+    pub async fn fold<A, F>(self, init: A, f: impl FnMut(A, T) -> F) -> (A, S)
+    where
+        F: Future<Output = A>,
+    {
+        let pop = |deq: Self| {
+            deq.pop().map(|queue| match queue {
+                BoundedQueue::Item(item, rest) => Ok((item, rest)),
+                BoundedQueue::Closed(session) => Err(session),
+            })
+        };
+        fold_with(self, init, pop, f).await
+    }
+
+    /// Runs the provided asynchronous function for each item from the queue. Next iteration
+    /// does not start before the previous one finishes. Returns the continuation `S`.
+    #[must_use]
+    pub async fn for_each<F>(self, mut f: impl FnMut(T) -> F) -> S
+    where
+        F: Future<Output = ()>,
+    {
+        self.fold((), |(), item| f(item)).await.1
+    }
+}
+
+impl<T, const N: usize> BoundedDequeue<T, N, ()>
+where
+    T: marker::Send + 'static,
+{
+    /// Accumulates all the items from the queue into a final result according to the provided
+    /// asynchronous function and the initial value. Returns the final result.
+    pub async fn fold1<A, F>(self, init: A, f: impl FnMut(A, T) -> F) -> A
+    where
+        F: Future<Output = A>,
+    {
+        self.fold(init, f).await.0
+    }
+
+    /// Runs the provided asynchronous function for each item from the queue. Next iteration
+    /// does not start before the previous one finishes.
+    pub async fn for_each1<F>(self, f: impl FnMut(T) -> F)
+    where
+        F: Future<Output = ()>,
+    {
+        self.for_each(f).await
+    }
+}
+
+impl<T, const N: usize, S: Session> BoundedEnqueue<T, N, S>
+where
+    T: marker::Send + 'static,
+{
+    /// Closes the queue, signaling to the other side that no more items will be pushed. Returns
+    /// the continuation `S`. Does not wait for the outstanding credits.
+    #[must_use]
+    pub fn close(self) -> S {
+        S::fork_sync(|dual| self.enq.send1(Bounded::Closed(dual)))
+    }
+
+    /// Pushes a value of type `T` into the queue. If `N` items are already in transit, waits for
+    /// the oldest of them to be popped first. A capacity `N` of zero behaves the same as one.
+    pub async fn push(mut self, item: T) -> Self {
+        while self.credits.len() >= N.max(1) {
+            if let Some(credit) = self.credits.pop_front() {
+                let _ = credit.await;
+            }
+        }
+        let (tx, rx) = oneshot::channel();
+        self.credits.push_back(rx);
+        Self {
+            enq: Send::fork_sync(|recv| {
+                self.enq
+                    .send1(Bounded::Item(item, tx, BoundedDequeue { deq: recv }))
+            }),
+            credits: self.credits,
+        }
+    }
+}
+
+impl<T, const N: usize> BoundedEnqueue<T, N, ()>
+where
+    T: marker::Send + 'static,
+{
+    /// Closes the queue, signaling to the other side that no more items will be pushed.
+    pub fn close1(self) {
+        self.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::tests::{endpoints, push};
+    use futures::{executor::block_on, task::noop_waker_ref};
+    use std::task::{Context, Poll};
+
+    #[test]
+    fn push_waits_for_a_credit_once_full() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let (deq, enq) = endpoints::<BoundedDequeue<i32, 2>>();
+        let enq = block_on(enq.push(1));
+        let enq = block_on(enq.push(2));
+
+        let mut third = Box::pin(enq.push(3));
+        assert!(third.poll_unpin(&mut cx).is_pending());
+        let BoundedQueue::Item(1, deq) = block_on(deq.pop()) else {
+            panic!("expected the first item");
+        };
+        let Poll::Ready(enq) = third.poll_unpin(&mut cx) else {
+            panic!("expected a credit");
+        };
+        enq.close1();
+
+        assert_eq!(block_on(deq.fold1(Vec::new(), push)), [2, 3]);
+    }
+
+    #[test]
+    fn zero_capacity_behaves_as_one() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let (deq, enq) = endpoints::<BoundedDequeue<i32, 0>>();
+        let enq = block_on(enq.push(1));
+
+        let mut second = Box::pin(enq.push(2));
+        assert!(second.poll_unpin(&mut cx).is_pending());
+        let BoundedQueue::Item(1, deq) = block_on(deq.pop()) else {
+            panic!("expected the first item");
+        };
+        let Poll::Ready(enq) = second.poll_unpin(&mut cx) else {
+            panic!("expected a credit");
+        };
+        enq.close1();
+        assert!(matches!(block_on(deq.pop()), BoundedQueue::Item(2, _)));
+    }
+}
@@ -0,0 +1,204 @@
+use super::{Dequeue, Enqueue, Queue};
+use crate::exchange::Recv;
+use futures::{
+    future::{BoxFuture, Shared},
+    FutureExt,
+};
+use std::{borrow::Borrow, collections::HashMap, hash::Hash, marker};
+
+/// A keyed collection of [`Enqueue`]s, for pushing items to specific queues or to all of them
+/// at once.
+///
+/// Removing or replacing a queue closes it. Use [`close`](Self::close) to close all the remaining queues.
+///
+/// Must not be dropped.
+#[must_use]
+pub struct Broadcaster<K, T> {
+    queues: HashMap<K, Option<Enqueue<T>>>,
+}
+
+impl<K, T> Default for Broadcaster<K, T> {
+    fn default() -> Self {
+        Self {
+            queues: HashMap::new(),
+        }
+    }
+}
+
+impl<K, T> Broadcaster<K, T>
+where
+    K: Eq + Hash,
+    T: marker::Send + 'static,
+{
+    /// Creates an empty [`Broadcaster`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a queue under a key. If a queue was already present under the key, it's closed.
+    pub fn insert(&mut self, key: K, enq: Enqueue<T>) {
+        if let Some(old) = self.queues.insert(key, Some(enq)).flatten() {
+            old.close1();
+        }
+    }
+
+    /// Removes the queue under a key and closes it. Returns whether there was such a queue.
+    pub fn remove<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        match self.queues.remove(key).flatten() {
+            Some(enq) => {
+                enq.close1();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether there is a queue under a key.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.queues.contains_key(key)
+    }
+
+    /// Returns an iterator over the keys of all the queues.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.queues.keys()
+    }
+
+    /// Returns the number of queues.
+    pub fn len(&self) -> usize {
+        self.queues.len()
+    }
+
+    /// Returns whether there are no queues.
+    pub fn is_empty(&self) -> bool {
+        self.queues.is_empty()
+    }
+
+    /// Pushes an item into the queue under a key. If there's no such queue, the item is returned
+    /// back.
+    pub fn send_to<Q>(&mut self, key: &Q, item: T) -> Result<(), T>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        match self.queues.get_mut(key) {
+            Some(slot) => {
+                push(slot, item);
+                Ok(())
+            }
+            None => Err(item),
+        }
+    }
+
+    /// Pushes a clone of an item into every queue.
+    pub fn broadcast(&mut self, item: T)
+    where
+        T: Clone,
+    {
+        for slot in self.queues.values_mut() {
+            push(slot, item.clone());
+        }
+    }
+
+    /// Closes all the queues.
+    pub fn close(self) {
+        for enq in self.queues.into_values().flatten() {
+            enq.close1();
+        }
+    }
+}
+
+fn push<T: marker::Send + 'static>(slot: &mut Option<Enqueue<T>>, item: T) {
+    let enq = slot.take().expect("missing queue");
+    *slot = Some(enq.push(item));
+}
+
+impl<T> Dequeue<T, ()>
+where
+    T: Clone + marker::Send + Sync + 'static,
+{
+    /// Duplicates the queue into `n` independent queues, each producing all the items. The items
+    /// are popped from the original queue as soon as any of the new queues needs them, and kept
+    /// until all the new queues have produced them.
+    pub fn tee(self, n: usize) -> Vec<Dequeue<T, ()>> {
+        let node = TeeNode::new(self);
+        (0..n).map(|_| node.clone().into_dequeue()).collect()
+    }
+}
+
+struct TeeNode<T>(Shared<TeeFuture<T>>);
+
+type TeeFuture<T> = BoxFuture<'static, Option<(T, TeeNode<T>)>>;
+
+impl<T> Clone for TeeNode<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> TeeNode<T>
+where
+    T: Clone + marker::Send + Sync + 'static,
+{
+    fn new(deq: Dequeue<T, ()>) -> Self {
+        Self(
+            async move {
+                match deq.pop().await {
+                    Queue::Item(item, rest) => Some((item, Self::new(rest))),
+                    Queue::Closed(()) => None,
+                }
+            }
+            .boxed()
+            .shared(),
+        )
+    }
+
+    fn into_dequeue(self) -> Dequeue<T, ()> {
+        Dequeue {
+            deq: Recv::lazy(async move {
+                match self.0.await {
+                    Some((item, next)) => (Queue::Item(item, next.into_dequeue()), ()),
+                    None => (Queue::Closed(()), ()),
+                }
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{queue::tests::push, Session};
+    use futures::executor::block_on;
+
+    #[test]
+    fn broadcaster_pushes_to_one_or_all_queues() {
+        let mut broadcaster = Broadcaster::new();
+        let a = Dequeue::fork_sync(|enq| broadcaster.insert("a", enq));
+        let b = Dequeue::fork_sync(|enq| broadcaster.insert("b", enq));
+        broadcaster.broadcast(1);
+        assert_eq!(broadcaster.send_to("a", 2), Ok(()));
+        assert_eq!(broadcaster.send_to("c", 3), Err(3));
+        assert!(broadcaster.remove("b"));
+        broadcaster.broadcast(4);
+        broadcaster.close();
+
+        assert_eq!(block_on(a.fold1(Vec::new(), push)), [1, 2, 4]);
+        assert_eq!(block_on(b.fold1(Vec::new(), push)), [1]);
+    }
+
+    #[test]
+    fn tee_produces_all_items_in_every_queue() {
+        let mut queues = Dequeue::from_iter1(1..=3).tee(2).into_iter();
+        let (first, second) = (queues.next().unwrap(), queues.next().unwrap());
+        assert_eq!(block_on(first.fold1(Vec::new(), push)), [1, 2, 3]);
+        assert_eq!(block_on(second.fold1(Vec::new(), push)), [1, 2, 3]);
+    }
+}
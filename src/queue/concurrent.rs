@@ -0,0 +1,226 @@
+use super::{Dequeue, PopFuture, Queue};
+use crate::{exchange::Recv, Session};
+use futures::{
+    future,
+    stream::{FuturesOrdered, FuturesUnordered},
+    Future, FutureExt, Stream, StreamExt,
+};
+use std::{
+    marker,
+    task::{Context, Poll},
+};
+
+impl<T, S: Session> Dequeue<T, S>
+where
+    T: marker::Send + 'static,
+{
+    /// Runs the provided asynchronous function for each item from the queue, with up to `limit`
+    /// of them running concurrently. A `limit` of zero means no limit. Returns the continuation `S`
+    /// once all the items are processed.
+    #[must_use]
+    pub async fn for_each_concurrent<F>(self, limit: usize, f: impl FnMut(T) -> F) -> S
+    where
+        F: Future<Output = ()>,
+    {
+        let mut state = Concurrent::new(self, limit, f, FuturesUnordered::new());
+        while let Some(()) = future::poll_fn(|cx| state.poll_next(cx)).await {}
+        state.closed.take().expect("missing continuation")
+    }
+
+    /// Transforms each item from the queue by the provided asynchronous function, with up to
+    /// `limit` of them running concurrently. A `limit` of zero means no limit. The results are
+    /// produced in the order of the original items. The continuation `S` is preserved.
+    pub fn buffered<U, F, Fut>(self, limit: usize, f: F) -> Dequeue<U, S>
+    where
+        U: marker::Send + 'static,
+        F: FnMut(T) -> Fut + marker::Send + 'static,
+        Fut: Future<Output = U> + marker::Send + 'static,
+    {
+        concurrent(Concurrent::new(self, limit, f, FuturesOrdered::new()))
+    }
+
+    /// Transforms each item from the queue by the provided asynchronous function, with up to
+    /// `limit` of them running concurrently. A `limit` of zero means no limit. The results are
+    /// produced in the order of completion. The continuation `S` is preserved.
+    pub fn buffer_unordered<U, F, Fut>(self, limit: usize, f: F) -> Dequeue<U, S>
+    where
+        U: marker::Send + 'static,
+        F: FnMut(T) -> Fut + marker::Send + 'static,
+        Fut: Future<Output = U> + marker::Send + 'static,
+    {
+        concurrent(Concurrent::new(self, limit, f, FuturesUnordered::new()))
+    }
+}
+
+impl<T> Dequeue<T, ()>
+where
+    T: marker::Send + 'static,
+{
+    /// Runs the provided asynchronous function for each item from the queue, with up to `limit`
+    /// of them running concurrently. A `limit` of zero means no limit. Finishes once all the items
+    /// are processed.
+    pub async fn for_each_concurrent1<F>(self, limit: usize, f: impl FnMut(T) -> F)
+    where
+        F: Future<Output = ()>,
+    {
+        self.for_each_concurrent(limit, f).await
+    }
+}
+
+struct Concurrent<T, S: Session, F, I> {
+    source: Option<PopFuture<T, S>>,
+    closed: Option<S>,
+    limit: usize,
+    f: F,
+    in_flight: I,
+}
+
+trait InFlight<Fut: Future>: Stream<Item = Fut::Output> + Unpin {
+    fn push(&mut self, future: Fut);
+    fn len(&self) -> usize;
+}
+
+impl<Fut: Future> InFlight<Fut> for FuturesOrdered<Fut> {
+    fn push(&mut self, future: Fut) {
+        self.push_back(future)
+    }
+
+    fn len(&self) -> usize {
+        FuturesOrdered::len(self)
+    }
+}
+
+impl<Fut: Future> InFlight<Fut> for FuturesUnordered<Fut> {
+    fn push(&mut self, future: Fut) {
+        FuturesUnordered::push(self, future)
+    }
+
+    fn len(&self) -> usize {
+        FuturesUnordered::len(self)
+    }
+}
+
+impl<T, S: Session, F, I> Concurrent<T, S, F, I>
+where
+    T: marker::Send + 'static,
+{
+    fn new(deq: Dequeue<T, S>, limit: usize, f: F, in_flight: I) -> Self {
+        Self {
+            source: Some(Box::pin(deq.pop())),
+            closed: None,
+            limit,
+            f,
+            in_flight,
+        }
+    }
+
+    /// Starts processing as many items as the limit allows, then polls for the next output.
+    /// Produces [`None`] once the source is closed and all the items have been processed.
+    fn poll_next<Fut>(&mut self, cx: &mut Context<'_>) -> Poll<Option<Fut::Output>>
+    where
+        F: FnMut(T) -> Fut,
+        Fut: Future,
+        I: InFlight<Fut>,
+    {
+        while self.limit == 0 || self.in_flight.len() < self.limit {
+            let Some(source) = &mut self.source else {
+                break;
+            };
+            match source.poll_unpin(cx) {
+                Poll::Ready(Queue::Item(item, rest)) => {
+                    self.in_flight.push((self.f)(item));
+                    self.source = Some(Box::pin(rest.pop()));
+                }
+                Poll::Ready(Queue::Closed(session)) => {
+                    self.closed = Some(session);
+                    self.source = None;
+                }
+                Poll::Pending => break,
+            }
+        }
+        match self.in_flight.poll_next_unpin(cx) {
+            Poll::Ready(Some(output)) => Poll::Ready(Some(output)),
+            Poll::Ready(None) if self.source.is_none() => Poll::Ready(None),
+            _ => Poll::Pending,
+        }
+    }
+}
+
+fn concurrent<T, S: Session, U, F, Fut, I>(mut state: Concurrent<T, S, F, I>) -> Dequeue<U, S>
+where
+    T: marker::Send + 'static,
+    U: marker::Send + 'static,
+    F: FnMut(T) -> Fut + marker::Send + 'static,
+    Fut: Future<Output = U>,
+    I: InFlight<Fut> + marker::Send + 'static,
+{
+    Dequeue {
+        deq: Recv::lazy(async move {
+            match future::poll_fn(|cx| state.poll_next(cx)).await {
+                Some(output) => (Queue::Item(output, concurrent(state)), ()),
+                None => {
+                    let session = state.closed.take().expect("missing continuation");
+                    (Queue::Closed(session), ())
+                }
+            }
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::tests::push;
+    use futures::executor::block_on;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    /// Stays pending for `n` polls, waking itself each time.
+    fn yield_times(mut n: usize) -> impl Future<Output = ()> {
+        future::poll_fn(move |cx| {
+            if n == 0 {
+                return Poll::Ready(());
+            }
+            n -= 1;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        })
+    }
+
+    fn max_in_flight(limit: usize) -> usize {
+        let running = Arc::new(AtomicUsize::new(0));
+        let max = Arc::new(AtomicUsize::new(0));
+        let deq = Dequeue::from_iter1(0..10);
+        block_on(deq.for_each_concurrent1(limit, |_| {
+            let (running, max) = (running.clone(), max.clone());
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max.fetch_max(now, Ordering::SeqCst);
+                yield_times(2).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+            }
+        }));
+        max.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn for_each_concurrent_respects_the_limit() {
+        assert_eq!(max_in_flight(1), 1);
+        assert_eq!(max_in_flight(3), 3);
+        assert_eq!(max_in_flight(0), 10);
+    }
+
+    #[test]
+    fn buffered_keeps_the_original_order() {
+        let slowest_first = |i: usize| async move {
+            yield_times(4 - i).await;
+            i
+        };
+        let deq = Dequeue::from_iter1(0..5).buffered(0, slowest_first);
+        assert_eq!(block_on(deq.fold1(Vec::new(), push)), [0, 1, 2, 3, 4]);
+        let deq = Dequeue::from_iter1(0..5).buffer_unordered(0, slowest_first);
+        assert_eq!(block_on(deq.fold1(Vec::new(), push)), [4, 3, 2, 1, 0]);
+    }
+}
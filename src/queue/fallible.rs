@@ -0,0 +1,296 @@
+use super::{fold_with, Next};
+use crate::{
+    exchange::{Recv, Send},
+    Session,
+};
+use futures::{Future, FutureExt, Stream};
+use std::{
+    marker,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// Produces an arbitrary number of values of type `T`, then either proceeds according to `S`, or
+/// fails with an error of type `E`. Its dual is [`TryEnqueue<T, E, Dual<S>>`].
+///
+/// Use [`pop`](Self::pop) to obtain the next item of type `T` from the queue (if there is any),
+/// the continuation `S` if the queue has been closed, or the error `E` if it has failed. Use
+/// [`try_fold`](Self::try_fold) or [`try_for_each`](Self::try_for_each) to process the values
+/// more ergonomically.
+#[must_use]
+pub struct TryDequeue<T, E, S: Session = ()> {
+    deq: Recv<TryQueue<T, E, S>>,
+}
+
+/// Accepts an arbitrary number of values of type `T`, then either proceeds according to `S`, or
+/// fails with an error of type `E`. Its dual is [`TryDequeue<T, E, Dual<S>>`].
+///
+/// Use [`push`](Self::push) to send a value over the queue. To stop sending values and obtain the
+/// continuation `S`, use [`close`](Self::close), or [`close1`](Self::close1) if `S` is `()` (the
+/// empty session). To abort with an error instead, use [`fail`](Self::fail).
+#[must_use]
+pub struct TryEnqueue<T, E, S: Session = ()> {
+    enq: Send<TryQueue<T, E, S::Dual>>,
+}
+
+/// The result of [`TryDequeue::pop`].
+pub enum TryQueue<T, E, S: Session = ()> {
+    Item(T, TryDequeue<T, E, S>),
+    Closed(S),
+    Failed(E),
+}
+
+impl<T, E, S: Session> Session for TryDequeue<T, E, S>
+where
+    T: marker::Send + 'static,
+    E: marker::Send + 'static,
+{
+    type Dual = TryEnqueue<T, E, S::Dual>;
+
+    fn fork_sync(f: impl FnOnce(Self::Dual)) -> Self {
+        Self {
+            deq: Recv::fork_sync(|send| f(TryEnqueue { enq: send })),
+        }
+    }
+
+    fn link(self, dual: Self::Dual) {
+        self.deq.link(dual.enq)
+    }
+}
+
+impl<T, E, S: Session> Session for TryEnqueue<T, E, S>
+where
+    T: marker::Send + 'static,
+    E: marker::Send + 'static,
+{
+    type Dual = TryDequeue<T, E, S::Dual>;
+
+    fn fork_sync(f: impl FnOnce(Self::Dual)) -> Self {
+        Self {
+            enq: Send::fork_sync(|recv| f(TryDequeue { deq: recv })),
+        }
+    }
+
+    fn link(self, dual: Self::Dual) {
+        self.enq.link(dual.deq)
+    }
+}
+
+impl<T, E, S: Session> TryDequeue<T, E, S>
+where
+    T: marker::Send + 'static,
+    E: marker::Send + 'static,
+{
+    /// Waits to receive the next item of type `T` pushed in the queue, the continuation `S`
+    /// if the queue has been closed, or the error `E` if it has failed.
+    #[must_use]
+    pub async fn pop(self) -> TryQueue<T, E, S> {
+        self.deq.recv1().await
+    }
+
+    /// Accumulates all the items from the queue into a final result according to the provided
+    /// asynchronous function and the initial value. Returns the final result along with the
+    /// continuation `S`, or the error `E` if the queue has failed.
+    pub async fn try_fold<A, F>(self, init: A, f: impl FnMut(A, T) -> F) -> Result<(A, S), E>
+    where
+        F: Future<Output = A>,
+    {
+        let pop = |deq: Self| {
+            deq.pop().map(|queue| match queue {
+                TryQueue::Item(item, rest) => Ok((item, rest)),
+                TryQueue::Closed(session) => Err(Ok(session)),
+                TryQueue::Failed(error) => Err(Err(error)),
+            })
+        };
+        let (accum, end) = fold_with(self, init, pop, f).await;
+        end.map(|session| (accum, session))
+    }
+
+    /// Runs the provided asynchronous function for each item from the queue. Next iteration
+    /// does not start before the previous one finishes. Returns the continuation `S`, or the
+    /// error `E` if the queue has failed.
+    pub async fn try_for_each<F>(self, mut f: impl FnMut(T) -> F) -> Result<S, E>
+    where
+        F: Future<Output = ()>,
+    {
+        self.try_fold((), |(), item| f(item))
+            .await
+            .map(|((), session)| session)
+    }
+
+    /// Turns a [`TryDequeue`] into a standard [`TryStream`](futures::TryStream) of [`Next<T, S>`],
+    /// producing all items from the queue before producing its final continuation, or the error.
+    #[must_use]
+    pub fn into_stream(self) -> TryDequeueStream<T, E, S> {
+        TryDequeueStream {
+            future: Some(Box::pin(self.pop())),
+        }
+    }
+}
+
+impl<T, E> TryDequeue<T, E, ()>
+where
+    T: marker::Send + 'static,
+    E: marker::Send + 'static,
+{
+    /// Accumulates all the items from the queue into a final result according to the provided
+    /// asynchronous function and the initial value. Returns the final result, or the error `E`
+    /// if the queue has failed.
+    pub async fn try_fold1<A, F>(self, init: A, f: impl FnMut(A, T) -> F) -> Result<A, E>
+    where
+        F: Future<Output = A>,
+    {
+        self.try_fold(init, f).await.map(|(accum, ())| accum)
+    }
+
+    /// Runs the provided asynchronous function for each item from the queue. Next iteration
+    /// does not start before the previous one finishes. Returns the error `E` if the queue
+    /// has failed.
+    pub async fn try_for_each1<F>(self, f: impl FnMut(T) -> F) -> Result<(), E>
+    where
+        F: Future<Output = ()>,
+    {
+        self.try_for_each(f).await
+    }
+
+    /// Turns a [`TryDequeue`] without a continuation into a standard
+    /// [`TryStream`](futures::TryStream).
+    #[must_use]
+    pub fn into_stream1(self) -> TryDequeueStream1<T, E> {
+        TryDequeueStream1 {
+            future: Some(Box::pin(self.pop())),
+        }
+    }
+}
+
+impl<T, E, S: Session> TryEnqueue<T, E, S>
+where
+    T: marker::Send + 'static,
+    E: marker::Send + 'static,
+{
+    /// Closes the queue, signaling to the other side that no more items will be pushed. Returns
+    /// the continuation `S`.
+    #[must_use]
+    pub fn close(self) -> S {
+        S::fork_sync(|dual| self.enq.send1(TryQueue::Closed(dual)))
+    }
+
+    /// Ends the queue with an error, signaling to the other side that no more items will be
+    /// pushed, and the continuation will not follow.
+    pub fn fail(self, error: E) {
+        self.enq.send1(TryQueue::Failed(error))
+    }
+
+    /// Pushes a value of type `T` into the queue. The items will be received in the same order
+    /// as they were pushed.
+    pub fn push(self, item: T) -> Self {
+        Self::fork_sync(|dual| self.enq.send1(TryQueue::Item(item, dual)))
+    }
+}
+
+impl<T, E> TryEnqueue<T, E, ()>
+where
+    T: marker::Send + 'static,
+    E: marker::Send + 'static,
+{
+    /// Closes the queue, signaling to the other side that no more items will be pushed.
+    pub fn close1(self) {
+        self.close()
+    }
+}
+
+type TryPopFuture<T, E, S> =
+    Pin<Box<dyn Future<Output = TryQueue<T, E, S>> + marker::Send + 'static>>;
+
+/// A [`TryStream`](futures::TryStream) of [`Next<T, S>`] producing all items from the queue before
+/// producing its final continuation, or the error if the queue has failed.
+pub struct TryDequeueStream<T, E, S: Session> {
+    future: Option<TryPopFuture<T, E, S>>,
+}
+
+impl<T, E, S: Session> Stream for TryDequeueStream<T, E, S>
+where
+    T: marker::Send + 'static,
+    E: marker::Send + 'static,
+{
+    type Item = Result<Next<T, S>, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let Some(future) = &mut self.future else {
+            return Poll::Ready(None);
+        };
+        match future.poll_unpin(cx) {
+            Poll::Ready(TryQueue::Item(value, next)) => {
+                self.future = Some(Box::pin(next.pop()));
+                Poll::Ready(Some(Ok(Next::Item(value))))
+            }
+            Poll::Ready(TryQueue::Closed(session)) => {
+                self.future = None;
+                Poll::Ready(Some(Ok(Next::Closed(session))))
+            }
+            Poll::Ready(TryQueue::Failed(error)) => {
+                self.future = None;
+                Poll::Ready(Some(Err(error)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// A [`TryStream`](futures::TryStream) producing all items from a [`TryDequeue`], or the error if
+/// the queue has failed.
+pub struct TryDequeueStream1<T, E> {
+    future: Option<TryPopFuture<T, E, ()>>,
+}
+
+impl<T, E> Stream for TryDequeueStream1<T, E>
+where
+    T: marker::Send + 'static,
+    E: marker::Send + 'static,
+{
+    type Item = Result<T, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let Some(future) = &mut self.future else {
+            return Poll::Ready(None);
+        };
+        match future.poll_unpin(cx) {
+            Poll::Ready(TryQueue::Item(value, next)) => {
+                self.future = Some(Box::pin(next.pop()));
+                Poll::Ready(Some(Ok(value)))
+            }
+            Poll::Ready(TryQueue::Closed(())) => {
+                self.future = None;
+                Poll::Ready(None)
+            }
+            Poll::Ready(TryQueue::Failed(error)) => {
+                self.future = None;
+                Poll::Ready(Some(Err(error)))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::tests::push;
+    use futures::{executor::block_on, StreamExt};
+
+    #[test]
+    fn try_fold_ends_with_the_continuation_or_the_error() {
+        let closed = TryDequeue::<i32, &str>::fork_sync(|enq| enq.push(1).push(2).close1());
+        assert_eq!(block_on(closed.try_fold1(Vec::new(), push)), Ok(vec![1, 2]));
+
+        let failed = TryDequeue::<i32, &str>::fork_sync(|enq| enq.push(1).fail("broken"));
+        assert_eq!(block_on(failed.try_fold1(Vec::new(), push)), Err("broken"));
+    }
+
+    #[test]
+    fn stream_stops_after_the_error() {
+        let failed = TryDequeue::<i32, &str>::fork_sync(|enq| enq.push(1).fail("broken"));
+        let items = block_on(failed.into_stream1().collect::<Vec<_>>());
+        assert_eq!(items, [Ok(1), Err("broken")]);
+    }
+}
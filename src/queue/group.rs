@@ -0,0 +1,75 @@
+use super::{Dequeue, Enqueue, Queue};
+use crate::{exchange::Recv, Session};
+use std::{collections::HashMap, hash::Hash, marker};
+
+impl<K, T, S: Session> Dequeue<(K, T), S>
+where
+    K: Eq + Hash + Clone + marker::Send + 'static,
+    T: marker::Send + 'static,
+{
+    /// Splits the queue of keyed items into a separate queue for each key. A new inner queue is
+    /// produced along with its key the first time the key appears, and all the following items
+    /// with the same key are pushed into it. All the inner queues are closed once the original
+    /// queue closes. The continuation `S` is preserved.
+    ///
+    /// The items are pushed into the inner queues as the outer queue is being popped, so it has
+    /// to keep being popped for the inner queues to make progress.
+    pub fn group_by_key(self) -> Dequeue<(K, Dequeue<T>), S> {
+        grouped(self, HashMap::new())
+    }
+}
+
+fn grouped<K, T, S: Session>(
+    mut deq: Dequeue<(K, T), S>,
+    mut groups: HashMap<K, Enqueue<T>>,
+) -> Dequeue<(K, Dequeue<T>), S>
+where
+    K: Eq + Hash + Clone + marker::Send + 'static,
+    T: marker::Send + 'static,
+{
+    Dequeue {
+        deq: Recv::lazy(async move {
+            loop {
+                match deq.pop().await {
+                    Queue::Item((key, item), rest) => {
+                        deq = rest;
+                        if let Some((key, enq)) = groups.remove_entry(&key) {
+                            groups.insert(key, enq.push(item));
+                            continue;
+                        }
+                        let group = Dequeue::fork_sync(|enq: Enqueue<T>| {
+                            groups.insert(key.clone(), enq.push(item));
+                        });
+                        break (Queue::Item((key, group), grouped(deq, groups)), ());
+                    }
+                    Queue::Closed(session) => {
+                        for enq in groups.into_values() {
+                            enq.close1();
+                        }
+                        break (Queue::Closed(session), ());
+                    }
+                }
+            }
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::queue::{tests::push, Dequeue};
+    use futures::executor::block_on;
+
+    #[test]
+    fn group_by_key_splits_items_in_order_of_first_appearance() {
+        let deq = Dequeue::from_iter1([("a", 1), ("b", 2), ("a", 3), ("c", 4), ("b", 5)]);
+        let groups = block_on(deq.group_by_key().fold1(Vec::new(), push));
+        let keys = groups.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+        assert_eq!(keys, ["a", "b", "c"]);
+
+        let items = groups
+            .into_iter()
+            .map(|(_, group)| block_on(group.fold1(Vec::new(), push)))
+            .collect::<Vec<_>>();
+        assert_eq!(items, [vec![1, 3], vec![2, 5], vec![4]]);
+    }
+}
@@ -0,0 +1,244 @@
+use super::{fold_with, Dequeue, Enqueue, PopFuture, Queue};
+use crate::{exchange::Recv, Session};
+use futures::{future, Future, FutureExt};
+use std::{
+    collections::BinaryHeap,
+    marker,
+    sync::{Mutex, PoisonError},
+    task::Poll,
+};
+
+/// Produces an arbitrary number of values of type `T`, then proceeds according to `S`. Its dual
+/// is [`PriorityEnqueue<T, Dual<S>>`].
+///
+/// Use [`pop`](Self::pop) to obtain the greatest item of type `T` out of those pushed so far (if
+/// there is any), or the continuation `S` if all the values have already been popped. To attach
+/// priorities to items that are not ordered themselves, push pairs of a priority and an item.
+#[must_use]
+pub struct PriorityDequeue<T, S: Session = ()> {
+    source: Option<Mutex<PopFuture<T, S>>>,
+    closed: Option<S>,
+    heap: BinaryHeap<T>,
+}
+
+/// Accepts an arbitrary number of values of type `T`, then proceeds according to `S`. Its dual
+/// is [`PriorityDequeue<T, Dual<S>>`].
+///
+/// Use [`push`](Self::push) to send a value over the queue. Pushing is non-blocking. To stop
+/// sending values and obtain the continuation `S`, use [`close`](Self::close), or
+/// [`close1`](Self::close1) if `S` is `()` (the empty session).
+#[must_use]
+pub struct PriorityEnqueue<T, S: Session = ()> {
+    enq: Enqueue<T, S>,
+}
+
+/// The result of [`PriorityDequeue::pop`].
+pub enum PriorityQueue<T, S: Session = ()> {
+    Item(T, PriorityDequeue<T, S>),
+    Closed(S),
+}
+
+impl<T, S: Session> Session for PriorityDequeue<T, S>
+where
+    T: Ord + marker::Send + 'static,
+{
+    type Dual = PriorityEnqueue<T, S::Dual>;
+
+    fn fork_sync(f: impl FnOnce(Self::Dual)) -> Self {
+        Self::new(Dequeue::fork_sync(|enq| f(PriorityEnqueue { enq })))
+    }
+
+    fn link(self, dual: Self::Dual) {
+        let enq = dual.enq.extend(self.heap.into_vec());
+        match (self.source, self.closed) {
+            (Some(source), _) => enq.link(Dequeue {
+                deq: Recv::lazy(
+                    source
+                        .into_inner()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .map(|queue| (queue, ())),
+                ),
+            }),
+            (None, Some(session)) => session.link(enq.close()),
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+impl<T, S: Session> Session for PriorityEnqueue<T, S>
+where
+    T: Ord + marker::Send + 'static,
+{
+    type Dual = PriorityDequeue<T, S::Dual>;
+
+    fn fork_sync(f: impl FnOnce(Self::Dual)) -> Self {
+        Self {
+            enq: Enqueue::fork_sync(|deq| f(PriorityDequeue::new(deq))),
+        }
+    }
+
+    fn link(self, dual: Self::Dual) {
+        dual.link(self)
+    }
+}
+
+impl<T, S: Session> PriorityDequeue<T, S>
+where
+    T: Ord + marker::Send + 'static,
+{
+    fn new(deq: Dequeue<T, S>) -> Self {
+        Self {
+            source: Some(Mutex::new(Box::pin(deq.pop()))),
+            closed: None,
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    /// Waits until there is at least one item of type `T` pushed in the queue, and returns the
+    /// greatest of the pushed items. Returns the continuation `S` if the queue has been closed
+    /// and all the items have been popped.
+    #[must_use]
+    pub async fn pop(mut self) -> PriorityQueue<T, S> {
+        future::poll_fn(|cx| {
+            while let Some(source) = &mut self.source {
+                let source = source.get_mut().unwrap_or_else(PoisonError::into_inner);
+                match source.poll_unpin(cx) {
+                    Poll::Ready(Queue::Item(item, rest)) => {
+                        self.heap.push(item);
+                        *source = Box::pin(rest.pop());
+                    }
+                    Poll::Ready(Queue::Closed(session)) => {
+                        self.closed = Some(session);
+                        self.source = None;
+                    }
+                    Poll::Pending => break,
+                }
+            }
+            if !self.heap.is_empty() || self.source.is_none() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+        match self.heap.pop() {
+            Some(item) => PriorityQueue::Item(item, self),
+            None => PriorityQueue::Closed(self.closed.take().expect("missing continuation")),
+        }
+    }
+
+    /// Accumulates all the items from the queue into a final result according to the provided
+    /// asynchronous function and the initial value. Returns the final result along with the
+    /// continuation `S`.
+    #[must_use]
+    pub async fn fold<A, F>(self, init: A, f: impl FnMut(A, T) -> F) -> (A, S)
+    where
+        F: Future<Output = A>,
+    {
+        let pop = |deq: Self| {
+            deq.pop().map(|queue| match queue {
+                PriorityQueue::Item(item, rest) => Ok((item, rest)),
+                PriorityQueue::Closed(session) => Err(session),
+            })
+        };
+        fold_with(self, init, pop, f).await
+    }
+
+    /// Runs the provided asynchronous function for each item from the queue. Next iteration
+    /// does not start before the previous one finishes. Returns the continuation `S`.
+    #[must_use]
+    pub async fn for_each<F>(self, mut f: impl FnMut(T) -> F) -> S
+    where
+        F: Future<Output = ()>,
+    {
+        self.fold((), |(), item| f(item)).await.1
+    }
+}
+
+impl<T> PriorityDequeue<T, ()>
+where
+    T: Ord + marker::Send + 'static,
+{
+    /// Accumulates all the items from the queue into a final result according to the provided
+    /// asynchronous function and the initial value. Returns the final result.
+    pub async fn fold1<A, F>(self, init: A, f: impl FnMut(A, T) -> F) -> A
+    where
+        F: Future<Output = A>,
+    {
+        self.fold(init, f).await.0
+    }
+
+    /// Runs the provided asynchronous function for each item from the queue. Next iteration
+    /// does not start before the previous one finishes.
+    pub async fn for_each1<F>(self, f: impl FnMut(T) -> F)
+    where
+        F: Future<Output = ()>,
+    {
+        self.for_each(f).await
+    }
+}
+
+impl<T, S: Session> PriorityEnqueue<T, S>
+where
+    T: Ord + marker::Send + 'static,
+{
+    /// Closes the queue, signaling to the other side that no more items will be pushed. Returns
+    /// the continuation `S`.
+    #[must_use]
+    pub fn close(self) -> S {
+        self.enq.close()
+    }
+
+    /// Pushes a value of type `T` into the queue.
+    pub fn push(self, item: T) -> Self {
+        Self {
+            enq: self.enq.push(item),
+        }
+    }
+}
+
+impl<T> PriorityEnqueue<T, ()>
+where
+    T: Ord + marker::Send + 'static,
+{
+    /// Closes the queue, signaling to the other side that no more items will be pushed.
+    pub fn close1(self) {
+        self.close()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::tests::{endpoints, push};
+    use futures::executor::block_on;
+
+    #[test]
+    fn pop_produces_the_greatest_item_pushed_so_far() {
+        let (deq, enq) = endpoints::<PriorityDequeue<i32>>();
+        let enq = enq.push(3).push(1).push(2);
+        let PriorityQueue::Item(3, deq) = block_on(deq.pop()) else {
+            panic!("expected the greatest item");
+        };
+        enq.push(5).close1();
+        assert_eq!(block_on(deq.fold1(Vec::new(), push)), [5, 2, 1]);
+    }
+
+    #[test]
+    fn dequeue_is_sync() {
+        fn assert_sync<T: Sync>() {}
+        assert_sync::<PriorityDequeue<i32, Recv<i32>>>();
+    }
+
+    #[test]
+    fn link_keeps_the_buffered_items() {
+        let (deq, enq) = endpoints::<PriorityDequeue<i32>>();
+        let enq = enq.push(1).push(3);
+        let PriorityQueue::Item(3, deq) = block_on(deq.pop()) else {
+            panic!("expected the greatest item");
+        };
+        let relay = PriorityDequeue::<i32>::fork_sync(|dual| deq.link(dual));
+        enq.push(2).close1();
+        assert_eq!(block_on(relay.fold1(Vec::new(), push)), [2, 1]);
+    }
+}
@@ -0,0 +1,264 @@
+use super::{Dequeue, PopFuture, Queue};
+use crate::{exchange::Recv, runtimes::Timer, Session};
+use futures::{future, future::BoxFuture, FutureExt};
+use std::{
+    marker, mem,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+impl<T, S: Session> Dequeue<T, S>
+where
+    T: marker::Send + 'static,
+{
+    /// Groups the items from the queue into vectors of at most `max` items. A vector is produced
+    /// once it's full, or once `duration` has passed since its first item was popped, whichever
+    /// comes first. The continuation `S` is preserved.
+    ///
+    /// Panics if `max` is zero.
+    pub fn chunks_timeout(
+        self,
+        max: usize,
+        duration: Duration,
+        timer: impl Timer,
+    ) -> Dequeue<Vec<T>, S> {
+        assert!(max != 0, "chunk size must be non-zero");
+        let mut chunk = Vec::new();
+        timed(Timed::new(self, timer), move |state, cx| loop {
+            match state.poll_item(cx) {
+                Poll::Ready(Some(item)) => {
+                    if chunk.is_empty() {
+                        state.start_sleep(duration);
+                    }
+                    chunk.push(item);
+                    if chunk.len() >= max {
+                        state.sleep = None;
+                        return Poll::Ready(Some(mem::take(&mut chunk)));
+                    }
+                }
+                Poll::Ready(None) => {
+                    state.sleep = None;
+                    return Poll::Ready((!chunk.is_empty()).then(|| mem::take(&mut chunk)));
+                }
+                Poll::Pending if !chunk.is_empty() && !state.sleeping(cx) => {
+                    return Poll::Ready(Some(mem::take(&mut chunk)))
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        })
+    }
+
+    /// Only produces an item from the queue once no other item has been popped for `duration`.
+    /// Superseded items are dropped. The last item is produced right away once the queue is
+    /// closed. The continuation `S` is preserved.
+    pub fn debounce(self, duration: Duration, timer: impl Timer) -> Self {
+        let mut pending = None;
+        timed(Timed::new(self, timer), move |state, cx| loop {
+            match state.poll_item(cx) {
+                Poll::Ready(Some(item)) => {
+                    pending = Some(item);
+                    state.start_sleep(duration);
+                }
+                Poll::Ready(None) => {
+                    state.sleep = None;
+                    return Poll::Ready(pending.take());
+                }
+                Poll::Pending if pending.is_some() && !state.sleeping(cx) => {
+                    return Poll::Ready(pending.take())
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        })
+    }
+
+    /// Produces at most one item from the queue per `duration`. Items popped sooner than
+    /// `duration` after the last produced item are dropped. The continuation `S` is preserved.
+    pub fn throttle(self, duration: Duration, timer: impl Timer) -> Self {
+        timed(Timed::new(self, timer), move |state, cx| loop {
+            match state.poll_item(cx) {
+                Poll::Ready(Some(_)) if state.sleeping(cx) => {}
+                Poll::Ready(Some(item)) => {
+                    state.start_sleep(duration);
+                    return Poll::Ready(Some(item));
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        })
+    }
+}
+
+struct Timed<T, S: Session, Tm> {
+    source: Option<PopFuture<T, S>>,
+    closed: Option<S>,
+    timer: Tm,
+    sleep: Option<BoxFuture<'static, ()>>,
+}
+
+impl<T, S: Session, Tm: Timer> Timed<T, S, Tm>
+where
+    T: marker::Send + 'static,
+{
+    fn new(deq: Dequeue<T, S>, timer: Tm) -> Self {
+        Self {
+            source: Some(Box::pin(deq.pop())),
+            closed: None,
+            timer,
+            sleep: None,
+        }
+    }
+
+    /// Polls for the next item from the source. Produces [`None`] once the source is closed.
+    fn poll_item(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let Some(source) = &mut self.source else {
+            return Poll::Ready(None);
+        };
+        match source.poll_unpin(cx) {
+            Poll::Ready(Queue::Item(item, rest)) => {
+                self.source = Some(Box::pin(rest.pop()));
+                Poll::Ready(Some(item))
+            }
+            Poll::Ready(Queue::Closed(session)) => {
+                self.closed = Some(session);
+                self.source = None;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn start_sleep(&mut self, duration: Duration) {
+        self.sleep = Some(self.timer.sleep(duration));
+    }
+
+    /// Returns whether the current sleep, if any, is still in progress.
+    fn sleeping(&mut self, cx: &mut Context<'_>) -> bool {
+        let Some(sleep) = &mut self.sleep else {
+            return false;
+        };
+        if sleep.poll_unpin(cx).is_pending() {
+            return true;
+        }
+        self.sleep = None;
+        false
+    }
+}
+
+fn timed<T, S: Session, Tm, U, F>(mut state: Timed<T, S, Tm>, mut step: F) -> Dequeue<U, S>
+where
+    T: marker::Send + 'static,
+    U: marker::Send + 'static,
+    Tm: Timer,
+    F: FnMut(&mut Timed<T, S, Tm>, &mut Context<'_>) -> Poll<Option<U>> + marker::Send + 'static,
+{
+    Dequeue {
+        deq: Recv::lazy(async move {
+            match future::poll_fn(|cx| step(&mut state, cx)).await {
+                Some(output) => (Queue::Item(output, timed(state, step)), ()),
+                None => {
+                    let session = state.closed.take().expect("missing continuation");
+                    (Queue::Closed(session), ())
+                }
+            }
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::tests::{endpoints, push};
+    use futures::{channel::oneshot, executor::block_on, task::noop_waker_ref};
+    use std::sync::{Arc, Mutex};
+
+    /// Sleeps that only complete once [`fire`](Self::fire) is called.
+    #[derive(Default)]
+    struct ManualTimer(Arc<Mutex<Vec<oneshot::Sender<()>>>>);
+
+    impl ManualTimer {
+        fn timer(&self) -> impl Timer {
+            let wakes = self.0.clone();
+            move |_| {
+                let (wake, woken) = oneshot::channel();
+                wakes.lock().unwrap().push(wake);
+                woken.map(|_| ())
+            }
+        }
+
+        fn fire(&self) {
+            for wake in self.0.lock().unwrap().drain(..) {
+                let _ = wake.send(());
+            }
+        }
+    }
+
+    fn start_pop<T: marker::Send + 'static>(deq: Dequeue<T>) -> PopFuture<T, ()> {
+        Box::pin(deq.pop())
+    }
+
+    fn poll_pop<T: marker::Send + 'static>(pop: &mut PopFuture<T, ()>) -> Poll<Queue<T, ()>> {
+        pop.poll_unpin(&mut Context::from_waker(noop_waker_ref()))
+    }
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn chunks_timeout_produces_full_or_timed_out_chunks() {
+        let timer = ManualTimer::default();
+        let (deq, enq) = endpoints::<Dequeue<i32>>();
+        let deq = deq.chunks_timeout(3, SECOND, timer.timer());
+        let enq = enq.push(1);
+
+        let mut pop = start_pop(deq);
+        assert!(poll_pop(&mut pop).is_pending());
+        timer.fire();
+        let Poll::Ready(Queue::Item(chunk, deq)) = poll_pop(&mut pop) else {
+            panic!("expected a timed out chunk");
+        };
+        assert_eq!(chunk, [1]);
+
+        enq.push(2).push(3).push(4).push(5).close1();
+        let chunks = block_on(deq.fold1(Vec::new(), push));
+        assert_eq!(chunks, [vec![2, 3, 4], vec![5]]);
+    }
+
+    #[test]
+    fn debounce_produces_the_last_item_of_a_burst() {
+        let timer = ManualTimer::default();
+        let (deq, enq) = endpoints::<Dequeue<i32>>();
+        let deq = deq.debounce(SECOND, timer.timer());
+        let enq = enq.push(1).push(2);
+
+        let mut pop = start_pop(deq);
+        assert!(poll_pop(&mut pop).is_pending());
+        timer.fire();
+        let Poll::Ready(Queue::Item(2, deq)) = poll_pop(&mut pop) else {
+            panic!("expected the last item");
+        };
+
+        enq.push(3).close1();
+        assert_eq!(block_on(deq.fold1(Vec::new(), push)), [3]);
+    }
+
+    #[test]
+    fn throttle_drops_items_until_the_sleep_is_over() {
+        let timer = ManualTimer::default();
+        let (deq, enq) = endpoints::<Dequeue<i32>>();
+        let deq = deq.throttle(SECOND, timer.timer());
+        let enq = enq.push(1).push(2);
+
+        let Poll::Ready(Queue::Item(1, deq)) = poll_pop(&mut start_pop(deq)) else {
+            panic!("expected the first item");
+        };
+        let mut pop = start_pop(deq);
+        assert!(poll_pop(&mut pop).is_pending());
+        timer.fire();
+        let enq = enq.push(3);
+        let Poll::Ready(Queue::Item(3, deq)) = poll_pop(&mut pop) else {
+            panic!("expected an item after the sleep");
+        };
+
+        enq.push(4).close1();
+        assert_eq!(block_on(deq.fold1(Vec::new(), push)), []);
+    }
+}
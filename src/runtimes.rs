@@ -1,4 +1,114 @@
 //! Asynchronous forking functions for different `async` runtimes.
+//!
+//! Time-based combinators, such as [`Dequeue::chunks_timeout`](crate::queue::Dequeue::chunks_timeout),
+//! take a [`Timer`]. Any function from [`Duration`] to a future is a [`Timer`], so each runtime's
+//! own `sleep` can be used directly. Each module here also provides a `sleep` function.
+
+use futures::{
+    channel::oneshot,
+    future::{self, BoxFuture, Either},
+    Future, FutureExt,
+};
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    sync::{mpsc, OnceLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Creates futures completing after a given duration.
+pub trait Timer: Send + Sync + 'static {
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+impl<F, Fut> Timer for F
+where
+    F: Fn(Duration) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        self(duration).boxed()
+    }
+}
+
+/// Completes after a given duration, measured on a single timer thread shared by all the sleeps.
+/// Works with any executor.
+fn thread_sleep(duration: Duration) -> impl Future<Output = ()> + Send + 'static {
+    let (wake, woken) = oneshot::channel();
+    if let Some(deadline) = Instant::now().checked_add(duration) {
+        let _ = timer_thread().send(Sleeper { deadline, wake });
+    }
+    woken.then(|result| match result {
+        Ok(()) => Either::Left(future::ready(())),
+        Err(oneshot::Canceled) => Either::Right(future::pending()),
+    })
+}
+
+struct Sleeper {
+    deadline: Instant,
+    wake: oneshot::Sender<()>,
+}
+
+impl PartialEq for Sleeper {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for Sleeper {}
+
+impl PartialOrd for Sleeper {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Sleeper {
+    // Reversed, so that the heap pops the earliest deadline first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.deadline.cmp(&self.deadline)
+    }
+}
+
+fn timer_thread() -> &'static mpsc::Sender<Sleeper> {
+    static TIMER: OnceLock<mpsc::Sender<Sleeper>> = OnceLock::new();
+    TIMER.get_or_init(|| {
+        let (sender, sleepers) = mpsc::channel();
+        thread::Builder::new()
+            .name("par-timer".into())
+            .spawn(move || run_timer(sleepers))
+            .expect("failed to spawn timer thread");
+        sender
+    })
+}
+
+fn run_timer(sleepers: mpsc::Receiver<Sleeper>) {
+    let mut heap = BinaryHeap::<Sleeper>::new();
+    let mut pruned_len = 0;
+    loop {
+        let now = Instant::now();
+        while heap.peek().is_some_and(|sleeper| sleeper.deadline <= now) {
+            let _ = heap.pop().expect("missing sleeper").wake.send(());
+        }
+        if heap.len() > 2 * pruned_len.max(64) {
+            heap.retain(|sleeper| !sleeper.wake.is_canceled());
+            pruned_len = heap.len();
+        }
+        let sleeper = match heap.peek() {
+            Some(next) => match sleepers.recv_timeout(next.deadline - now) {
+                Ok(sleeper) => sleeper,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return,
+            },
+            None => match sleepers.recv() {
+                Ok(sleeper) => sleeper,
+                Err(mpsc::RecvError) => return,
+            },
+        };
+        heap.push(sleeper);
+    }
+}
 
 #[cfg(feature = "runtime-tokio")]
 pub mod tokio {
@@ -11,11 +121,19 @@ pub mod tokio {
     {
         S::fork_sync(|session| drop(tokio::spawn(f(session))))
     }
+
+    pub use tokio::time::sleep;
 }
 
 pub mod spawn {
     use crate::Session;
     use futures::{task::SpawnExt, Future};
+    use std::time::Duration;
+
+    /// Completes after a given duration, measured on a timer thread shared by all the sleeps.
+    pub fn sleep(duration: Duration) -> impl Future<Output = ()> + Send + 'static {
+        super::thread_sleep(duration)
+    }
 
     pub trait Fork {
         fn fork<S: Session, F>(&self, f: impl FnOnce(S::Dual) -> F) -> S
@@ -36,6 +154,12 @@ pub mod spawn {
 pub mod local_spawn {
     use crate::Session;
     use futures::{task::LocalSpawnExt, Future};
+    use std::time::Duration;
+
+    /// Completes after a given duration, measured on a timer thread shared by all the sleeps.
+    pub fn sleep(duration: Duration) -> impl Future<Output = ()> + Send + 'static {
+        super::thread_sleep(duration)
+    }
 
     pub trait Fork {
         fn fork<S: Session, F>(&self, f: impl FnOnce(S::Dual) -> F) -> S
@@ -52,3 +176,45 @@ pub mod local_spawn {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, stream::FuturesUnordered, task::noop_waker_ref, StreamExt};
+    use std::task::Context;
+
+    #[test]
+    fn sleeps_complete_in_the_order_of_deadlines() {
+        let start = Instant::now();
+        let sleeps = [60, 20, 40]
+            .map(|millis| thread_sleep(Duration::from_millis(millis)).map(move |()| millis));
+        let sleeps = sleeps.into_iter().collect::<FuturesUnordered<_>>();
+        assert_eq!(block_on(sleeps.collect::<Vec<_>>()), [20, 40, 60]);
+        assert!(start.elapsed() >= Duration::from_millis(60));
+    }
+
+    #[test]
+    fn overflowing_sleep_never_completes() {
+        let mut sleep = Box::pin(thread_sleep(Duration::MAX));
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert!(sleep.poll_unpin(&mut cx).is_pending());
+    }
+
+    #[test]
+    fn timer_skips_cancelled_sleepers_and_stops_without_senders() {
+        let (sender, sleepers) = mpsc::channel();
+        let timer = thread::spawn(move || run_timer(sleepers));
+        let deadline = Instant::now() + Duration::from_millis(10);
+        for _ in 0..1000 {
+            let (wake, woken) = oneshot::channel();
+            drop(woken);
+            sender.send(Sleeper { deadline, wake }).unwrap();
+        }
+        let (wake, woken) = oneshot::channel();
+        sender.send(Sleeper { deadline, wake }).unwrap();
+        assert_eq!(block_on(woken), Ok(()));
+
+        drop(sender);
+        timer.join().unwrap();
+    }
+}
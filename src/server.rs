@@ -4,12 +4,15 @@
 //!
 //! The communication structure of a server is composed of three parts:
 //!
-//! - [`Server`] is the central manager of the clients. Use [`poll`](Server::poll) to handle
-//!   new connections (coming from [proxies](Proxy)), as well as resumptions of existing ones.
-//!   Use [`suspend`](Server::suspend) to create [`Connection`] handles for later resumption.
+//! - [`Server`] is the central manager of the clients. Use [`poll`](Server::poll) or
+//!   [`next_event`](Server::next_event) to handle new connections (coming from [proxies](Proxy)),
+//!   as well as resumptions of existing ones. Use [`suspend`](Server::suspend) to create
+//!   [`Connection`] handles for later resumption. Alternatively, implement [`ServerHandler`] and
+//!   let [`run`](Server::run) drive the event loop.
 //! - [`Proxy`] is a handle for initiating new connections. It can be dropped, or duplicated
 //!   using (atypical) [`clone`](Proxy::clone). Use [`connect`](Proxy::connect) to initiate
-//!   a connection protocol.
+//!   a connection protocol, or [`try_connect`](Proxy::try_connect) to respect the server's
+//!   [limits](ServerConfig).
 //! - [`Connection`] is an active, suspended connection to the server. It must not
 //!   be dropped, disconnecting from a server should only be done in a controlled way specified
 //!   in its interaction (resumption) protocol. Use [`resume`](Connection::resume) to enter the
@@ -34,21 +37,88 @@
 //! a [`Connection`] in the same scope, one could call [`resume`](Connection::resume) and get stuck
 //! on the resumption because the server would not have a chance to call [`poll`](Server::poll).
 //!
+//! ## Shutdown
+//!
+//! Use [`shutdown`](Server::shutdown) to stop accepting new connections. After that, connection
+//! initiations fail with [`ConnectError::ShuttingDown`] (see [`Proxy::try_connect`]), while suspended
+//! connections keep being resumed as usual, so that each of them can be ended by its resumption protocol.
+//! Once no suspended connections are left, polling the server returns [`None`].
+//!
+//! ## Notifications
+//!
+//! Only clients start interactions, by resuming their connections. For messages going the other way,
+//! suspend a connection with [`suspend_with_inbox`](Server::suspend_with_inbox) to give the client
+//! an inbox, then push to it with [`notify`](Server::notify) and [`notify_all`](Server::notify_all).
+//!
+//! Connections can also be put in groups, such as chat rooms or topic subscriptions. Use
+//! [`create_group`](Server::create_group) and [`join`](Server::join), then push to the whole group
+//! with [`notify_group`](Server::notify_group).
+//!
+//! ## Idle connections
+//!
+//! A client may hold on to a [`Connection`] and never resume it. Use
+//! [`set_idle_timeout`](Server::set_idle_timeout) to expire connections suspended for too long: the
+//! server hands their local data back with an [expiration event](Event::Expired), and the client's
+//! later [`try_resume`](Connection::try_resume) fails with [`ResumeError::Expired`].
+//!
+//! ## Sharding
+//!
+//! A [`Server`] handles all of its events on a single task. A [`ShardedServer`] spreads connections
+//! across several [shards](Shard), each a [`Server`] with its own connections, behind a single
+//! [`Proxy`]. Run each shard on its own task, and use [`Shard::send_to`] to pass messages between
+//! them.
+//!
+//! ## Routing
+//!
+//! A [`Router`] exposes several servers, possibly with different protocols, behind a single
+//! [`Proxy`]. The client sends a routing key or a branch choice first, and the router
+//! [links](Session::link) the rest of its session into a new connection of the chosen server.
+//!
 //! ## Correspondence to linear logic
 //!
 //! [Proxies](Proxy) are an implementation of coexponentials. While not a standard part of linear logic,
 //! they come from a nice paper from 2021,
 //! ['Client-server sessions in linear logic.'](https://dl.acm.org/doi/10.1145/3473567)
 
-use super::Session;
-use futures::{channel::mpsc, StreamExt};
-use std::collections::HashMap;
+use super::{
+    exchange,
+    queue::{Broadcaster, Dequeue},
+    runtimes::Timer,
+    Session,
+};
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{self, AbortHandle, Abortable, BoxFuture},
+    stream::FuturesUnordered,
+    Future, FutureExt, Stream, StreamExt,
+};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    error::Error,
+    fmt,
+    hash::{Hash, Hasher},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+mod router;
+mod sharded;
+mod slab;
+
+pub use router::Router;
+pub use sharded::{Shard, ShardEvent, ShardedServer};
+use slab::Slab;
 
 /// Listens to connection initiatioins (from a [`Proxy`]) and resumptions (from a [`Connection`]) and
 /// maintains local data for each active connection. Use [`suspend`](Self::suspend) to create or maintain
 /// an active connection.
 ///
-/// The three generic parameters are as follows:
+/// The generic parameters are as follows:
 ///
 /// - **`Connect`** -- The connection initiation protocol. Involves any communication needed for
 ///   establishing a valid connection, such as login details. A successful initiation should result in
@@ -65,18 +135,80 @@ use std::collections::HashMap;
 ///   identify the client, as well as carry state that's updated with each suspension. Only the server side
 ///   can access this data directly.
 ///
+/// - **`Notification`** -- Messages the server can push to active connections with
+///   [`notify`](Self::notify), without waiting for them to resume. Only used by connections suspended
+///   with [`suspend_with_inbox`](Self::suspend_with_inbox). Defaults to `()`.
+///
 /// Must not be dropped.
 #[must_use]
-pub struct Server<Connect, Resume, ConnectionData>
+pub struct Server<Connect, Resume, ConnectionData, Notification = ()>
+where
+    Connect: Session,
+    Resume: Session,
+{
+    config: ServerConfig,
+    shared: Arc<Shared>,
+    connects: mpsc::Receiver<ConnectRequest<Connect>>,
+    connects_done: bool,
+    turn: Queue,
+    served: u32,
+    resume_sender: mpsc::Sender<(Resume, ConnectionId)>,
+    resumes: mpsc::Receiver<(Resume, ConnectionId)>,
+    abandon_sender: mpsc::UnboundedSender<ConnectionId>,
+    abandoned: mpsc::UnboundedReceiver<ConnectionId>,
+    connections: Slab<Suspension<ConnectionData>>,
+    inboxes: Broadcaster<ConnectionId, Notification>,
+    groups: HashMap<GroupId, HashSet<ConnectionId>>,
+    next_group: u64,
+    idle_timeout: Option<(Duration, Box<dyn Timer>)>,
+    expirations: Mutex<FuturesUnordered<Abortable<Expiration>>>,
+}
+
+/// Handles [events](Event) of a [`Server`] in its event loop, run by [`Server::run`]. The handler
+/// itself holds the state of the server, and is returned once the event loop finishes.
+///
+/// Both methods receive a [`Suspender`] to create or maintain active connections.
+pub trait ServerHandler<Connect, Resume, ConnectionData, Notification = ()>
 where
     Connect: Session,
     Resume: Session,
 {
-    sender: Sender<Connect, Resume, usize>,
-    receiver: Receiver<Connect, Resume, usize>,
-    data: HashMap<usize, ConnectionData>,
-    next_id: usize,
-    free_ids: Vec<usize>,
+    /// Handles a [connection initiation event](Event::Connect).
+    fn on_connect(
+        &mut self,
+        server: Suspender<'_, Connect, Resume, ConnectionData, Notification>,
+        session: Connect,
+    ) -> impl Future<Output = ()> + Send;
+
+    /// Handles a [connection resumption event](Event::Resume).
+    fn on_resume(
+        &mut self,
+        server: Suspender<'_, Connect, Resume, ConnectionData, Notification>,
+        session: Resume,
+        data: ConnectionData,
+        id: ConnectionId,
+    ) -> impl Future<Output = ()> + Send;
+
+    /// Handles a [connection expiration event](Event::Expired). Drops the data by default.
+    fn on_expire(
+        &mut self,
+        server: Suspender<'_, Connect, Resume, ConnectionData, Notification>,
+        data: ConnectionData,
+        id: ConnectionId,
+    ) -> impl Future<Output = ()> + Send {
+        let _ = (server, data, id);
+        async {}
+    }
+}
+
+/// The capability to [suspend](Self::suspend) connections and access the data of suspended ones,
+/// passed to a [`ServerHandler`].
+pub struct Suspender<'a, Connect, Resume, ConnectionData, Notification = ()>
+where
+    Connect: Session,
+    Resume: Session,
+{
+    server: &'a mut Server<Connect, Resume, ConnectionData, Notification>,
 }
 
 /// A handle for initiating new connections with the corresponding [server](Server). Use
@@ -84,19 +216,24 @@ where
 ///
 /// Can be duplicated with [`Proxy::clone`], or dropped at will.
 pub struct Proxy<Connect: Session> {
-    connect: Box<dyn SenderFn<Connect::Dual>>,
+    routes: Arc<Routes<Connect::Dual>>,
 }
 
 /// A client's handle to an active suspended connection. Use [`resume`](Self::resume) to enter the
 /// server's event loop and continue interaction.
 ///
-/// Must not be dropped. Specify the resumption protocol to handle disconnecting.
+/// Must not be dropped, unless it has [expired](Event::Expired). Specify the resumption protocol to
+/// handle disconnecting. If it's dropped anyway, for example by a panicking client, the server ends
+/// the connection and drops its local data, without an event.
 #[must_use]
 pub struct Connection<Resume: Session> {
-    resume: Box<dyn FnOnce(Resume::Dual) + Send>,
+    resume: Option<Box<dyn FnOnce(Resume::Dual) + Send>>,
+    claimed: Arc<AtomicBool>,
+    id: ConnectionId,
+    abandon: mpsc::UnboundedSender<ConnectionId>,
 }
 
-/// Connection initiation or resumption event.
+/// Connection initiation, resumption, or expiration event.
 #[must_use]
 pub enum Event<Connect, Resume, ConnectionData>
 where
@@ -116,142 +253,1354 @@ where
 
         /// Local connection data previously assigned with [`suspend`](Server::suspend).
         data: ConnectionData,
+
+        /// Identifier of the connection, as returned by [`suspend`](Server::suspend).
+        id: ConnectionId,
     },
+
+    /// Connection expiration event. The connection stayed suspended for longer than the
+    /// [idle timeout](Server::set_idle_timeout) and is now ended.
+    Expired {
+        /// Local connection data previously assigned with [`suspend`](Server::suspend).
+        data: ConnectionData,
+
+        /// Identifier of the connection, as returned by [`suspend`](Server::suspend).
+        id: ConnectionId,
+    },
+}
+
+/// Identifies a connection of a [`Server`]. Returned by [`suspend`](Server::suspend) and reported
+/// with the [resumption event](Event::Resume).
+///
+/// An identifier is unique for the whole lifetime of a connection, including its repeated resumptions
+/// (see [`resuspend`](Server::resuspend)). Once the connection ends, its identifier is never reused.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ConnectionId {
+    index: usize,
+    generation: u64,
 }
 
-impl<Connect: Session, Resume: Session, ConnectionData> Server<Connect, Resume, ConnectionData> {
+impl fmt::Display for ConnectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+/// Identifies a group of connections of a [`Server`], such as a chat room. Returned by
+/// [`create_group`](Server::create_group).
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct GroupId(u64);
+
+/// Configuration of a [`Server`], used with [`Server::start_with`]. The default configuration
+/// imposes no limits and alternates between connection initiations and resumptions.
+///
+/// The limits only apply to connection attempts made with [`Proxy::try_connect`], which can be
+/// [rejected](ConnectError::Rejected). Attempts made with [`Proxy::connect`] are always accepted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ServerConfig {
+    /// Maximum number of connection attempts not yet received by the server. Further attempts are
+    /// rejected right away, without reaching the server.
+    pub max_pending: Option<usize>,
+
+    /// Maximum number of active connections, that is, connections with a [`ConnectionId`]. Attempts
+    /// received while the server has this many active connections are rejected.
+    ///
+    /// Accepted [connection initiations](Event::Connect) only count once they're suspended. The
+    /// limit is exact if each initiation is suspended (or dropped) before the server is polled
+    /// again, as with [`run`](Server::run). Otherwise, it's exceeded by at most the number of
+    /// initiations being handled at once.
+    pub max_connections: Option<usize>,
+
+    /// Order in which the server handles connection initiations and resumptions, when both are
+    /// waiting.
+    pub scheduling: Scheduling,
+}
+
+/// Order in which a [`Server`] handles waiting [connection initiations](Event::Connect) and
+/// [resumptions](Event::Resume). Both come in separate queues, and a flood of one kind only delays
+/// the other kind as much as the scheduling allows.
+///
+/// The resumptions of a single connection are always handled in order, regardless of the scheduling:
+/// a [`Connection`] can only be resumed once, and the next one is only created by suspending the
+/// connection again.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Scheduling {
+    /// Takes up to `connects` initiations, then up to `resumes` resumptions, and so on. A queue
+    /// with a zero weight is only served when the other one is empty.
+    WeightedRoundRobin {
+        /// Number of initiations handled in a row.
+        connects: u32,
+        /// Number of resumptions handled in a row.
+        resumes: u32,
+    },
+
+    /// Handles resumptions only when no initiations are waiting.
+    PreferConnects,
+
+    /// Handles initiations only when no resumptions are waiting. Keeps serving connected clients
+    /// under a flood of new connections.
+    PreferResumes,
+}
+
+impl Default for Scheduling {
+    fn default() -> Self {
+        Self::WeightedRoundRobin {
+            connects: 1,
+            resumes: 1,
+        }
+    }
+}
+
+/// Reason of a failed connection initiation, returned by [`Proxy::try_connect`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConnectError {
+    /// The server is [shutting down](Server::shutdown) and doesn't accept new connections.
+    ShuttingDown,
+
+    /// The server is at one of its [limits](ServerConfig). Trying again later may succeed.
+    Rejected,
+
+    /// The server no longer exists.
+    ServerGone,
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ShuttingDown => write!(f, "server is shutting down"),
+            Self::Rejected => write!(f, "connection rejected"),
+            Self::ServerGone => write!(f, "server dropped"),
+        }
+    }
+}
+
+impl Error for ConnectError {}
+
+/// Reason of a failed connection resumption, returned by [`Connection::try_resume`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ResumeError {
+    /// The connection stayed suspended for longer than the server's
+    /// [idle timeout](Server::set_idle_timeout) and has ended.
+    Expired,
+}
+
+impl fmt::Display for ResumeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Expired => write!(f, "connection expired"),
+        }
+    }
+}
+
+impl Error for ResumeError {}
+
+impl<Connect: Session, Resume: Session, ConnectionData, Notification: Send + 'static>
+    Server<Connect, Resume, ConnectionData, Notification>
+{
     /// Creates a new [`Server`] and passes a [`Proxy`] to it to the provided closure. Use the [`Proxy`]
     /// to initiate connections to the server and use the returned [`Server`] to [poll](Self::poll)
     /// [events](Event) of initiating and resuming connections.
     #[must_use]
     pub fn start(f: impl FnOnce(Proxy<Connect::Dual>)) -> Self {
-        let (tx, rx) = mpsc::channel(0);
-
-        let mut proxy_sender = Sender(tx.clone());
-        f(Proxy {
-            connect: Box::new(move |session| {
-                proxy_sender
-                    .0
-                    .try_send((proxy_sender.clone(), Event::Connect { session }))
-                    .expect("server dropped");
-            }),
-        });
+        Self::start_with(ServerConfig::default(), f)
+    }
 
-        Self {
-            sender: Sender(tx),
-            receiver: Receiver(rx),
-            data: HashMap::new(),
-            next_id: 0,
-            free_ids: Vec::new(),
-        }
+    /// Creates a new [`Server`] with the provided [configuration](ServerConfig). See
+    /// [`start`](Self::start).
+    pub fn start_with(config: ServerConfig, f: impl FnOnce(Proxy<Connect::Dual>)) -> Self {
+        let (server, route) = Self::create(config);
+        f(Proxy::new(vec![route]));
+        server
+    }
+
+    fn create(config: ServerConfig) -> (Self, Route<Connect>) {
+        let shared = Arc::new(Shared {
+            max_pending: config.max_pending,
+            pending: AtomicUsize::new(0),
+            shutting_down: AtomicBool::new(false),
+        });
+        let (connect, connects) = mpsc::channel(0);
+        let (resume_sender, resumes) = mpsc::channel(0);
+        let (abandon_sender, abandoned) = mpsc::unbounded();
+        let route = Route {
+            connect,
+            shared: Arc::clone(&shared),
+        };
+        let server = Self {
+            config,
+            shared,
+            connects,
+            connects_done: false,
+            turn: Queue::Connects,
+            served: 0,
+            resume_sender,
+            resumes,
+            abandon_sender,
+            abandoned,
+            connections: Slab::new(),
+            inboxes: Broadcaster::new(),
+            groups: HashMap::new(),
+            next_group: 0,
+            idle_timeout: None,
+            expirations: Mutex::new(FuturesUnordered::new()),
+        };
+        (server, route)
     }
 
     /// Creates or maintains an active connection and associates local data to it. The data will later
     /// come back with a [resumption event](Event::Resume). Use this method to pass a [`Connection`] to
     /// a client during the initiation and resumption protocols.
-    pub fn suspend(&mut self, data: ConnectionData, f: impl FnOnce(Connection<Resume::Dual>)) {
-        let sender = self.sender.clone();
-        let id = self.acquire_id();
-        self.data.insert(id, data);
+    ///
+    /// Each call creates a new connection with a new identifier. To maintain the identity of a resumed
+    /// connection, use [`resuspend`](Self::resuspend) instead.
+    ///
+    /// Returns the identifier of the suspended connection. While suspended, its data can be accessed
+    /// with [`get`](Self::get) and [`get_mut`](Self::get_mut).
+    pub fn suspend(
+        &mut self,
+        data: ConnectionData,
+        f: impl FnOnce(Connection<Resume::Dual>),
+    ) -> ConnectionId {
+        let id = self.connections.insert(Suspension { data, expiry: None });
+        self.connect_client(id, f);
+        id
+    }
+
+    /// Suspends a resumed connection again, keeping its identifier, along with its inbox and groups.
+    /// Use this method instead of [`suspend`](Self::suspend) when handling a
+    /// [resumption event](Event::Resume) to maintain the connection's identity across repeated
+    /// resumptions.
+    ///
+    /// A resumed connection keeps its identifier until the server is polled again. After that, or if
+    /// the identifier doesn't belong to a resumed connection, a new identifier is assigned, just like
+    /// with [`suspend`](Self::suspend). Returns the identifier of the suspended connection.
+    pub fn resuspend(
+        &mut self,
+        id: ConnectionId,
+        data: ConnectionData,
+        f: impl FnOnce(Connection<Resume::Dual>),
+    ) -> ConnectionId {
+        match self
+            .connections
+            .resuspend(id, Suspension { data, expiry: None })
+        {
+            Ok(()) => {
+                self.connect_client(id, f);
+                id
+            }
+            Err(Suspension { data, .. }) => self.suspend(data, f),
+        }
+    }
+
+    /// Like [`suspend`](Self::suspend), but also opens an inbox of the connection, passed to the closure
+    /// along with the [`Connection`]. The server pushes [notifications](Self::notify) to the inbox
+    /// for the whole lifetime of the connection, including its repeated
+    /// [resuspensions](Self::resuspend). The inbox is closed once the connection ends.
+    pub fn suspend_with_inbox(
+        &mut self,
+        data: ConnectionData,
+        f: impl FnOnce(Connection<Resume::Dual>, Dequeue<Notification>),
+    ) -> ConnectionId {
+        let id = self.connections.insert(Suspension { data, expiry: None });
+        let inbox = Dequeue::fork_sync(|inbox| self.inboxes.insert(id, inbox));
+        self.connect_client(id, |conn| f(conn, inbox));
+        id
+    }
+
+    /// Pushes a notification to the inbox of an active connection, opened by
+    /// [`suspend_with_inbox`](Self::suspend_with_inbox). Returns the notification back if the
+    /// connection has no inbox, or has ended.
+    pub fn notify(
+        &mut self,
+        id: ConnectionId,
+        notification: Notification,
+    ) -> Result<(), Notification> {
+        self.inboxes.send_to(&id, notification)
+    }
+
+    /// Pushes a notification to the inboxes of all the active connections.
+    pub fn notify_all(&mut self, notification: Notification)
+    where
+        Notification: Clone,
+    {
+        self.inboxes.broadcast(notification)
+    }
+
+    /// Creates a new, empty group of connections. Use [`join`](Self::join) to add connections to it.
+    pub fn create_group(&mut self) -> GroupId {
+        let group = GroupId(self.next_group);
+        self.next_group += 1;
+        self.groups.insert(group, HashSet::new());
+        group
+    }
+
+    /// Deletes a group. The connections in it are not affected. Returns `false` if there's no such
+    /// group.
+    pub fn delete_group(&mut self, group: GroupId) -> bool {
+        self.groups.remove(&group).is_some()
+    }
+
+    /// Adds an active connection, suspended or resumed, to a group. A connection can be in any number
+    /// of groups, and leaves all of them once it ends. Returns `false` if there's no such group or
+    /// connection.
+    pub fn join(&mut self, group: GroupId, id: ConnectionId) -> bool {
+        match self.groups.get_mut(&group) {
+            Some(members) if self.connections.contains(id) => {
+                members.insert(id);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes a connection from a group. Returns `false` if the connection wasn't in the group.
+    pub fn leave(&mut self, group: GroupId, id: ConnectionId) -> bool {
+        self.groups
+            .get_mut(&group)
+            .is_some_and(|members| members.remove(&id))
+    }
+
+    /// Returns an iterator over the connections in a group, along with their local data if they're
+    /// suspended. The iterator is empty if there's no such group.
+    pub fn group(
+        &self,
+        group: GroupId,
+    ) -> impl Iterator<Item = (ConnectionId, Option<&ConnectionData>)> {
+        self.groups.get(&group).into_iter().flatten().map(|&id| {
+            (
+                id,
+                self.connections.get(id).map(|suspension| &suspension.data),
+            )
+        })
+    }
+
+    /// Pushes a notification to the inboxes of all the connections in a group. Connections without
+    /// an inbox are skipped.
+    pub fn notify_group(&mut self, group: GroupId, notification: Notification)
+    where
+        Notification: Clone,
+    {
+        let Some(members) = self.groups.get(&group) else {
+            return;
+        };
+        for id in members {
+            let _ = self.inboxes.send_to(id, notification.clone());
+        }
+    }
+
+    fn end_connection(&mut self, id: ConnectionId) {
+        self.inboxes.remove(&id);
+        for members in self.groups.values_mut() {
+            members.remove(&id);
+        }
+    }
+
+    fn connect_client(&mut self, id: ConnectionId, f: impl FnOnce(Connection<Resume::Dual>)) {
+        let claimed = Arc::new(AtomicBool::new(false));
+        if let Some((timeout, timer)) = &self.idle_timeout {
+            let sleep = timer.sleep(*timeout);
+            let claimed = Arc::clone(&claimed);
+            let (expiry, registration) = AbortHandle::new_pair();
+            self.expirations_mut().push(Abortable::new(
+                async move {
+                    sleep.await;
+                    (id, claimed)
+                }
+                .boxed(),
+                registration,
+            ));
+            if let Some(suspension) = self.connections.get_mut(id) {
+                suspension.expiry = Some(expiry);
+            }
+        }
+        let mut sender = self.resume_sender.clone();
         f(Connection {
-            resume: Box::new(move |session| {
-                sender
-                    .0
-                    .clone()
-                    .try_send((sender, Event::Resume { session, data: id }))
-                    .expect("server dropped");
-            }),
+            resume: Some(Box::new(move |session| {
+                sender.try_send((session, id)).expect("server dropped");
+            })),
+            claimed,
+            id,
+            abandon: self.abandon_sender.clone(),
         })
     }
 
+    fn expirations_mut(&mut self) -> &mut FuturesUnordered<Abortable<Expiration>> {
+        self.expirations
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Sets the idle timeout of suspended connections, measured by the provided [`Timer`]. A connection
+    /// not resumed within the timeout after being suspended [expires](Event::Expired). Applies to
+    /// connections suspended from now on.
+    pub fn set_idle_timeout(&mut self, timeout: Duration, timer: impl Timer) {
+        self.idle_timeout = Some((timeout, Box::new(timer)));
+    }
+
+    /// Starts a graceful shutdown of the server. New connection initiations are rejected with
+    /// [`ConnectError::ShuttingDown`], except for those already sent, which still come as
+    /// [connection events](Event::Connect).
+    ///
+    /// Suspended connections are resumed as usual. Handle their final resumptions without suspending
+    /// them again, and the server will produce [`None`] once no suspended connections are left.
+    pub fn shutdown(&mut self) {
+        self.shared.shutting_down.store(true, Ordering::SeqCst);
+        self.connects.close();
+    }
+
+    /// Returns `true` if [`shutdown`](Self::shutdown) was called.
+    pub fn is_shutting_down(&self) -> bool {
+        self.shared.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Returns an iterator over all the suspended connections along with their local data.
+    pub fn connections(&self) -> impl Iterator<Item = (ConnectionId, &ConnectionData)> {
+        self.connections
+            .iter()
+            .map(|(id, suspension)| (id, &suspension.data))
+    }
+
+    /// Returns the local data of a suspended connection, if it's still suspended.
+    pub fn get(&self, id: ConnectionId) -> Option<&ConnectionData> {
+        self.connections.get(id).map(|suspension| &suspension.data)
+    }
+
+    /// Returns the local data of a suspended connection for modification, if it's still suspended.
+    /// The modified data will come back with its [resumption event](Event::Resume).
+    pub fn get_mut(&mut self, id: ConnectionId) -> Option<&mut ConnectionData> {
+        self.connections
+            .get_mut(id)
+            .map(|suspension| &mut suspension.data)
+    }
+
     /// Waits for the next connection initiation (from a [`Proxy`]) or resumption (from a [`Connection`]).
     /// Returns the corresponding event along with a new [`Server`] handle. In case no more [proxies](Proxy)
-    /// or [connections](Connection) exist, [`None`] is returned and the [`Server`] is dropped.
+    /// or [connections](Connection) exist, or the server has [shut down](Self::shutdown), [`None`] is
+    /// returned and the [`Server`] is dropped.
     #[must_use]
     pub async fn poll(mut self) -> Option<(Self, Event<Connect, Resume, ConnectionData>)> {
-        drop(self.sender);
-        match self.receiver.0.next().await {
-            Some((sender, trans)) => {
-                self.sender = sender;
-                let trans = match trans {
-                    Event::Connect { session } => Event::Connect { session },
-                    Event::Resume { session, data: id } => {
-                        self.release_id(id);
-                        let data = self.data.remove(&id).expect("missing connection data");
-                        Event::Resume { session, data }
-                    }
+        let event = self.next_event().await?;
+        Some((self, event))
+    }
+
+    /// Waits for the next connection initiation (from a [`Proxy`]) or resumption (from a [`Connection`])
+    /// without consuming the [`Server`]. In case no more [proxies](Proxy) or [connections](Connection)
+    /// exist, or the server has [shut down](Self::shutdown), [`None`] is returned and the [`Server`] is
+    /// finished.
+    ///
+    /// The [`Server`] is also a [`Stream`](futures::Stream) of [events](Event), which makes it easy to
+    /// combine with other futures and streams.
+    #[must_use]
+    pub async fn next_event(&mut self) -> Option<Event<Connect, Resume, ConnectionData>> {
+        future::poll_fn(|cx| self.poll_event(cx)).await
+    }
+
+    /// Runs the server's event loop, passing all the [events](Event) to the provided handler until no
+    /// more [proxies](Proxy) or [connections](Connection) exist, or the server has
+    /// [shut down](Self::shutdown). Each event is handled before the next
+    /// one is polled. Returns the handler with its final state.
+    pub async fn run<H>(mut self, mut handler: H) -> H
+    where
+        H: ServerHandler<Connect, Resume, ConnectionData, Notification>,
+    {
+        while let Some(event) = self.next_event().await {
+            let server = Suspender { server: &mut self };
+            match event {
+                Event::Connect { session } => handler.on_connect(server, session).await,
+                Event::Resume { session, data, id } => {
+                    handler.on_resume(server, session, data, id).await
+                }
+                Event::Expired { data, id } => handler.on_expire(server, data, id).await,
+            }
+        }
+        handler
+    }
+
+    /// Polls for the next connection initiation (from a [`Proxy`]) or resumption (from a [`Connection`]).
+    /// Produces [`None`] once no more [proxies](Proxy) or [connections](Connection) exist, or the server
+    /// has [shut down](Self::shutdown).
+    pub fn poll_event(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Event<Connect, Resume, ConnectionData>>> {
+        for id in self.connections.release_resumed() {
+            self.end_connection(id);
+        }
+        while let Poll::Ready(Some(id)) = self.abandoned.poll_next_unpin(cx) {
+            if let Some(Suspension { expiry, .. }) = self.connections.remove(id) {
+                if let Some(expiry) = expiry {
+                    expiry.abort();
+                }
+                self.end_connection(id);
+            }
+        }
+        while let Poll::Ready(Some(expired)) = self.expirations_mut().poll_next_unpin(cx) {
+            let Ok((id, claimed)) = expired else {
+                continue;
+            };
+            if !claimed.swap(true, Ordering::SeqCst) {
+                let Suspension { data, .. } = self
+                    .connections
+                    .remove(id)
+                    .expect("missing connection data");
+                self.end_connection(id);
+                return Poll::Ready(Some(Event::Expired { data, id }));
+            }
+        }
+        for queue in self.schedule() {
+            let event = match queue {
+                Queue::Connects => self.poll_connect(cx),
+                Queue::Resumes => self.poll_resume(cx),
+            };
+            if let Some(event) = event {
+                if queue != self.turn {
+                    self.turn = queue;
+                    self.served = 0;
+                }
+                self.served = self.served.saturating_add(1);
+                return Poll::Ready(Some(event));
+            }
+        }
+        if self.connects_done && self.connections.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn schedule(&mut self) -> [Queue; 2] {
+        match self.config.scheduling {
+            Scheduling::WeightedRoundRobin { connects, resumes } => {
+                let weight = |queue| match queue {
+                    Queue::Connects => connects,
+                    Queue::Resumes => resumes,
                 };
-                Some((self, trans))
+                if self.served >= weight(self.turn) {
+                    self.turn = self.turn.other();
+                    self.served = 0;
+                }
+                if weight(self.turn) == 0 {
+                    [self.turn.other(), self.turn]
+                } else {
+                    [self.turn, self.turn.other()]
+                }
             }
-            None => None,
+            Scheduling::PreferConnects => [Queue::Connects, Queue::Resumes],
+            Scheduling::PreferResumes => [Queue::Resumes, Queue::Connects],
         }
     }
 
-    fn acquire_id(&mut self) -> usize {
-        if let Some(id) = self.free_ids.pop() {
-            return id;
+    fn poll_connect(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Option<Event<Connect, Resume, ConnectionData>> {
+        while !self.connects_done {
+            match self.connects.poll_next_unpin(cx) {
+                Poll::Ready(Some((session, reply))) => {
+                    if let Some(reply) = reply {
+                        self.shared.pending.fetch_sub(1, Ordering::SeqCst);
+                        let accepted = self
+                            .config
+                            .max_connections
+                            .map_or(true, |max| self.connections.len() < max);
+                        if reply.send(accepted).is_err() || !accepted {
+                            continue;
+                        }
+                    }
+                    return Some(Event::Connect { session });
+                }
+                Poll::Ready(None) => self.connects_done = true,
+                Poll::Pending => break,
+            }
         }
-        let id = self.next_id;
-        self.next_id += 1;
-        id
+        None
     }
 
-    fn release_id(&mut self, id: usize) {
-        if id + 1 == self.next_id {
-            self.next_id = id;
-        } else {
-            self.free_ids.push(id);
+    fn poll_resume(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Option<Event<Connect, Resume, ConnectionData>> {
+        match self.resumes.poll_next_unpin(cx) {
+            Poll::Ready(Some((session, id))) => {
+                let Suspension { data, expiry } = self
+                    .connections
+                    .resume(id)
+                    .expect("missing connection data");
+                if let Some(expiry) = expiry {
+                    expiry.abort();
+                }
+                Some(Event::Resume { session, data, id })
+            }
+            Poll::Ready(None) | Poll::Pending => None,
         }
     }
 }
 
+impl<Connect: Session, Resume: Session, ConnectionData, Notification: Send + 'static> Unpin
+    for Server<Connect, Resume, ConnectionData, Notification>
+{
+}
+
+impl<Connect: Session, Resume: Session, ConnectionData, Notification: Send + 'static> Stream
+    for Server<Connect, Resume, ConnectionData, Notification>
+{
+    type Item = Event<Connect, Resume, ConnectionData>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_event(cx)
+    }
+}
+
+impl<Connect: Session, Resume: Session, ConnectionData, Notification: Send + 'static>
+    Suspender<'_, Connect, Resume, ConnectionData, Notification>
+{
+    /// Creates or maintains an active connection and associates local data to it. See
+    /// [`Server::suspend`].
+    pub fn suspend(
+        &mut self,
+        data: ConnectionData,
+        f: impl FnOnce(Connection<Resume::Dual>),
+    ) -> ConnectionId {
+        self.server.suspend(data, f)
+    }
+
+    /// Creates an active connection with an inbox. See [`Server::suspend_with_inbox`].
+    pub fn suspend_with_inbox(
+        &mut self,
+        data: ConnectionData,
+        f: impl FnOnce(Connection<Resume::Dual>, Dequeue<Notification>),
+    ) -> ConnectionId {
+        self.server.suspend_with_inbox(data, f)
+    }
+
+    /// Suspends a resumed connection again, keeping its identifier. See [`Server::resuspend`].
+    pub fn resuspend(
+        &mut self,
+        id: ConnectionId,
+        data: ConnectionData,
+        f: impl FnOnce(Connection<Resume::Dual>),
+    ) -> ConnectionId {
+        self.server.resuspend(id, data, f)
+    }
+
+    /// Returns an iterator over all the suspended connections along with their local data. See
+    /// [`Server::connections`].
+    pub fn connections(&self) -> impl Iterator<Item = (ConnectionId, &ConnectionData)> {
+        self.server.connections()
+    }
+
+    /// Returns the local data of a suspended connection for modification. See [`Server::get_mut`].
+    pub fn get_mut(&mut self, id: ConnectionId) -> Option<&mut ConnectionData> {
+        self.server.get_mut(id)
+    }
+
+    /// Pushes a notification to the inbox of an active connection. See [`Server::notify`].
+    pub fn notify(
+        &mut self,
+        id: ConnectionId,
+        notification: Notification,
+    ) -> Result<(), Notification> {
+        self.server.notify(id, notification)
+    }
+
+    /// Pushes a notification to the inboxes of all the active connections. See
+    /// [`Server::notify_all`].
+    pub fn notify_all(&mut self, notification: Notification)
+    where
+        Notification: Clone,
+    {
+        self.server.notify_all(notification)
+    }
+
+    /// Creates a new, empty group of connections. See [`Server::create_group`].
+    pub fn create_group(&mut self) -> GroupId {
+        self.server.create_group()
+    }
+
+    /// Deletes a group. See [`Server::delete_group`].
+    pub fn delete_group(&mut self, group: GroupId) -> bool {
+        self.server.delete_group(group)
+    }
+
+    /// Adds an active connection to a group. See [`Server::join`].
+    pub fn join(&mut self, group: GroupId, id: ConnectionId) -> bool {
+        self.server.join(group, id)
+    }
+
+    /// Removes a connection from a group. See [`Server::leave`].
+    pub fn leave(&mut self, group: GroupId, id: ConnectionId) -> bool {
+        self.server.leave(group, id)
+    }
+
+    /// Returns an iterator over the connections in a group. See [`Server::group`].
+    pub fn group(
+        &self,
+        group: GroupId,
+    ) -> impl Iterator<Item = (ConnectionId, Option<&ConnectionData>)> {
+        self.server.group(group)
+    }
+
+    /// Pushes a notification to the inboxes of all the connections in a group. See
+    /// [`Server::notify_group`].
+    pub fn notify_group(&mut self, group: GroupId, notification: Notification)
+    where
+        Notification: Clone,
+    {
+        self.server.notify_group(group, notification)
+    }
+
+    /// Starts a graceful shutdown of the server. See [`Server::shutdown`].
+    pub fn shutdown(&mut self) {
+        self.server.shutdown()
+    }
+
+    /// Returns `true` if the server is shutting down. See [`Server::is_shutting_down`].
+    pub fn is_shutting_down(&self) -> bool {
+        self.server.is_shutting_down()
+    }
+}
+
 impl<Connect: Session> Proxy<Connect> {
+    fn new(routes: Vec<Route<Connect::Dual>>) -> Self {
+        Self {
+            routes: Arc::new(Routes {
+                routes,
+                next: AtomicUsize::new(0),
+            }),
+        }
+    }
+
     /// Duplicates the [proxy](Proxy) into another scope. No two [proxies](Proxy) can ever see each
     /// other, but there can be an arbitrary number of them around.
     pub fn clone(&self, f: impl FnOnce(Self)) {
         f(Self {
-            connect: self.connect.clone(),
+            routes: Arc::clone(&self.routes),
         })
     }
 
     /// Initiates a new connection with the server. Returns the client's side of the connection
-    /// initiation protocol.
+    /// initiation protocol. With a [`ShardedServer`], the shards take turns.
+    ///
+    /// The server's [limits](ServerConfig) don't apply. Panics if the server is
+    /// [shutting down](Server::shutdown) or no longer exists. Use [`try_connect`](Self::try_connect)
+    /// to handle those cases.
     #[must_use]
     pub fn connect(self) -> Connect {
-        Connect::fork_sync(|dual| self.connect.send(dual))
+        self.routes.round_robin().connect()
+    }
+
+    /// Initiates a new connection with the server, waiting for the server to accept it. Returns the
+    /// client's side of the connection initiation protocol, or the reason of the failure. With a
+    /// [`ShardedServer`], the shards take turns.
+    ///
+    /// The connection is [rejected](ConnectError::Rejected) if the server is at one of its
+    /// [limits](ServerConfig), so the client can back off and try again later.
+    pub async fn try_connect(self) -> Result<Connect, ConnectError> {
+        self.routes.round_robin().try_connect().await
+    }
+}
+
+impl<Key, Connect> Proxy<exchange::Send<Key, Connect>>
+where
+    Key: Hash + Send + 'static,
+    Connect: Session,
+{
+    /// Initiates a new connection with the server whose initiation protocol starts with a key, and
+    /// sends the key. With a [`ShardedServer`], the shard is chosen by the key's hash, so connections
+    /// with equal keys always end up on the same shard. See [`connect`](Self::connect).
+    #[must_use]
+    pub fn connect_keyed(self, key: Key) -> Connect {
+        self.routes
+            .hashed(&key)
+            .connect::<exchange::Send<Key, Connect>>()
+            .send(key)
+    }
+
+    /// Like [`connect_keyed`](Self::connect_keyed), but waits for the server to accept the
+    /// connection. See [`try_connect`](Self::try_connect).
+    pub async fn try_connect_keyed(self, key: Key) -> Result<Connect, ConnectError> {
+        let session: exchange::Send<Key, Connect> = self.routes.hashed(&key).try_connect().await?;
+        Ok(session.send(key))
     }
 }
 
 impl<Resume: Session> Connection<Resume> {
     /// Resumes the [connection](Connection), entering the server's event loop. Returns the client's
     /// side of the connection resumption protocol.
+    ///
+    /// Panics if the connection has [expired](Event::Expired). Use [`try_resume`](Self::try_resume) to
+    /// handle that case.
     #[must_use]
     pub fn resume(self) -> Resume {
-        Resume::fork_sync(|dual| (self.resume)(dual))
+        self.try_resume().unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Resumes the [connection](Connection), unless it has [expired](Event::Expired). Returns the
+    /// client's side of the connection resumption protocol, or the reason of the failure.
+    pub fn try_resume(mut self) -> Result<Resume, ResumeError> {
+        if self.claimed.swap(true, Ordering::SeqCst) {
+            return Err(ResumeError::Expired);
+        }
+        let resume = self.resume.take().expect("connection resumed twice");
+        Ok(Resume::fork_sync(resume))
+    }
+}
+
+impl<Resume: Session> Drop for Connection<Resume> {
+    fn drop(&mut self) {
+        if !self.claimed.swap(true, Ordering::SeqCst) {
+            let _ = self.abandon.unbounded_send(self.id);
+        }
     }
 }
 
-struct Sender<C: Session, R: Session, D>(mpsc::Sender<Exchange<C, R, D>>);
-struct Receiver<C: Session, R: Session, D>(mpsc::Receiver<Exchange<C, R, D>>);
-type Exchange<C, R, D> = (Sender<C, R, D>, Event<C, R, D>);
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Queue {
+    Connects,
+    Resumes,
+}
+
+impl Queue {
+    fn other(self) -> Self {
+        match self {
+            Self::Connects => Self::Resumes,
+            Self::Resumes => Self::Connects,
+        }
+    }
+}
+
+type ConnectRequest<C> = (C, Option<oneshot::Sender<bool>>);
+
+type Expiration = BoxFuture<'static, (ConnectionId, Arc<AtomicBool>)>;
+
+struct Suspension<D> {
+    data: D,
+    expiry: Option<AbortHandle>,
+}
+
+struct Routes<C> {
+    routes: Vec<Route<C>>,
+    next: AtomicUsize,
+}
+
+struct Route<C> {
+    connect: mpsc::Sender<ConnectRequest<C>>,
+    shared: Arc<Shared>,
+}
+
+impl<C> Routes<C> {
+    fn round_robin(&self) -> &Route<C> {
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        &self.routes[next % self.routes.len()]
+    }
+
+    fn hashed(&self, key: &impl Hash) -> &Route<C> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.routes[(hasher.finish() % self.routes.len() as u64) as usize]
+    }
+}
+
+impl<C: Session> Route<C> {
+    fn connect<Client: Session<Dual = C>>(&self) -> Client {
+        Client::fork_sync(|dual| {
+            if let Err(error) = self.request(dual, None) {
+                panic!("{error}");
+            }
+        })
+    }
+
+    async fn try_connect<Client: Session<Dual = C>>(&self) -> Result<Client, ConnectError> {
+        if !self.shared.admit() {
+            return Err(ConnectError::Rejected);
+        }
+        let (reply, accepted) = oneshot::channel();
+        let mut result = Ok(());
+        let session = Client::fork_sync(|dual| result = self.request(dual, Some(reply)));
+        if let Err(error) = result {
+            self.shared.pending.fetch_sub(1, Ordering::SeqCst);
+            return Err(error);
+        }
+        match accepted.await {
+            Ok(true) => Ok(session),
+            Ok(false) => Err(ConnectError::Rejected),
+            Err(oneshot::Canceled) => Err(ConnectError::ServerGone),
+        }
+    }
 
-impl<C: Session, R: Session, D> Clone for Sender<C, R, D> {
-    fn clone(&self) -> Self {
-        Self(self.0.clone())
+    fn request(
+        &self,
+        session: C,
+        reply: Option<oneshot::Sender<bool>>,
+    ) -> Result<(), ConnectError> {
+        self.connect
+            .clone()
+            .try_send((session, reply))
+            .map_err(|_| {
+                if self.shared.shutting_down.load(Ordering::SeqCst) {
+                    ConnectError::ShuttingDown
+                } else {
+                    ConnectError::ServerGone
+                }
+            })
     }
 }
 
-trait SenderFn<T>: Send + Sync + 'static {
-    fn clone(&self) -> Box<dyn SenderFn<T>>;
-    fn send(self: Box<Self>, value: T);
+struct Shared {
+    max_pending: Option<usize>,
+    pending: AtomicUsize,
+    shutting_down: AtomicBool,
+}
+
+impl Shared {
+    fn admit(&self) -> bool {
+        self.pending
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| {
+                match self.max_pending {
+                    Some(max) if pending >= max => None,
+                    _ => Some(pending + 1),
+                }
+            })
+            .is_ok()
+    }
 }
 
-impl<T, F: FnOnce(T) + Send + Sync + Clone + 'static> SenderFn<T> for F {
-    fn clone(&self) -> Box<dyn SenderFn<T>> {
-        Box::new((self as &F).clone())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::tests::push;
+    use futures::{executor::block_on, task::noop_waker_ref};
+
+    type TestServer<D, N = ()> = Server<exchange::Recv<i32>, exchange::Recv<i32>, D, N>;
+
+    /// Polls the server once, without a waker.
+    fn poll<C: Session, R: Session, D, N: Send + 'static>(
+        server: &mut Server<C, R, D, N>,
+    ) -> Poll<Option<Event<C, R, D>>> {
+        server.poll_event(&mut Context::from_waker(noop_waker_ref()))
+    }
+
+    /// Suspends a new connection. Returns its identifier along with the client's handle.
+    fn suspend<C: Session, R: Session, D, N: Send + 'static>(
+        server: &mut Server<C, R, D, N>,
+        data: D,
+    ) -> (ConnectionId, Connection<R::Dual>) {
+        let mut connection = None;
+        let id = server.suspend(data, |conn| connection = Some(conn));
+        (id, connection.expect("missing connection"))
+    }
+
+    /// Polls the server once, expecting a resumption. Returns its data and identifier.
+    fn resumed<C: Session, R: Session, D, N: Send + 'static>(
+        server: &mut Server<C, R, D, N>,
+    ) -> (D, ConnectionId) {
+        match poll(server) {
+            Poll::Ready(Some(Event::Resume { data, id, .. })) => (data, id),
+            _ => panic!("expected a resumption"),
+        }
+    }
+
+    #[test]
+    fn server_is_sync() {
+        fn assert_sync<T: Sync>() {}
+        assert_sync::<Server<exchange::Recv<i32>, exchange::Recv<i32>, i32>>();
+        assert_sync::<Server<exchange::Recv<i32>, exchange::Recv<i32>, i32, i32>>();
+    }
+
+    #[test]
+    fn suspend_after_pending_poll() {
+        let mut proxy = None;
+        let mut server = TestServer::<i32>::start(|p| proxy = Some(p));
+        assert!(poll(&mut server).is_pending());
+
+        let (id, connection) = suspend(&mut server, 1);
+        drop(proxy);
+        assert!(poll(&mut server).is_pending());
+
+        connection.resume().send1(2);
+        assert_eq!(resumed(&mut server), (1, id));
+        assert!(matches!(poll(&mut server), Poll::Ready(None)));
+    }
+
+    #[test]
+    fn dropped_connection_ends() {
+        let mut server = TestServer::<i32>::start(drop);
+        let id = server.suspend(1, drop);
+        assert!(matches!(poll(&mut server), Poll::Ready(None)));
+        assert_eq!(server.get(id), None);
+    }
+
+    #[test]
+    fn resuspend_keeps_connection() {
+        let mut server = TestServer::<i32, i32>::start(drop);
+        let mut client = None;
+        let id = server.suspend_with_inbox(0, |conn, inbox| client = Some((conn, inbox)));
+        let (connection, inbox) = client.expect("missing connection");
+        connection.resume().send1(1);
+        let (data, resumed_id) = resumed(&mut server);
+        assert_eq!(resumed_id, id);
+
+        let mut connection = None;
+        assert_eq!(
+            server.resuspend(id, data + 1, |conn| connection = Some(conn)),
+            id
+        );
+        assert!(poll(&mut server).is_pending());
+        assert_eq!(server.get(id), Some(&1));
+        assert_eq!(server.notify(id, 7), Ok(()));
+
+        connection.expect("missing connection").resume().send1(2);
+        assert_eq!(resumed(&mut server), (1, id));
+        assert!(matches!(poll(&mut server), Poll::Ready(None)));
+        assert_eq!(block_on(inbox.fold1(Vec::new(), push)), [7]);
+    }
+
+    #[test]
+    fn data_of_suspended_connections_can_be_modified() {
+        let mut server = TestServer::<i32>::start(drop);
+        let (a, first) = suspend(&mut server, 1);
+        let (b, second) = suspend(&mut server, 2);
+        first.resume().send1(0);
+        assert_eq!(resumed(&mut server).1, a);
+        assert_eq!(server.connections().collect::<Vec<_>>(), [(b, &2)]);
+        assert_eq!(server.get_mut(a), None);
+
+        *server.get_mut(b).expect("missing data") += 10;
+        second.resume().send1(0);
+        assert_eq!(resumed(&mut server), (12, b));
+        assert!(matches!(poll(&mut server), Poll::Ready(None)));
+    }
+
+    #[test]
+    fn scheduling_interleaves_waiting_events() {
+        fn order(scheduling: Scheduling) -> String {
+            let mut cx = Context::from_waker(noop_waker_ref());
+            let config = ServerConfig {
+                scheduling,
+                ..ServerConfig::default()
+            };
+            let mut proxy = None;
+            let mut server =
+                Server::<exchange::Recv<i32>, exchange::Recv<i32>, ()>::start_with(config, |p| {
+                    proxy = Some(p);
+                });
+            let proxy = proxy.expect("missing proxy");
+
+            let mut connections = Vec::new();
+            for _ in 0..3 {
+                server.suspend((), |conn| connections.push(conn));
+            }
+            for (i, connection) in connections.into_iter().enumerate() {
+                proxy.clone(|proxy| proxy.connect().send1(i as i32));
+                connection.resume().send1(i as i32);
+            }
+            drop(proxy);
+
+            let mut order = String::new();
+            while let Poll::Ready(Some(event)) = server.poll_event(&mut cx) {
+                order.push(match event {
+                    Event::Connect { .. } => 'C',
+                    Event::Resume { .. } => 'R',
+                    Event::Expired { .. } => 'E',
+                });
+            }
+            order
+        }
+
+        let weighted = Scheduling::WeightedRoundRobin {
+            connects: 2,
+            resumes: 1,
+        };
+        assert_eq!(order(weighted), "CCRCRR");
+        assert_eq!(order(Scheduling::default()), "CRCRCR");
+        assert_eq!(order(Scheduling::PreferConnects), "CCCRRR");
+        assert_eq!(order(Scheduling::PreferResumes), "RRRCCC");
+        let resumes_only = Scheduling::WeightedRoundRobin {
+            connects: 0,
+            resumes: 1,
+        };
+        assert_eq!(order(resumes_only), "RRRCCC");
+    }
+
+    #[test]
+    fn try_connect_is_rejected_at_the_limits() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let config = ServerConfig {
+            max_pending: Some(1),
+            max_connections: Some(1),
+            ..ServerConfig::default()
+        };
+        let mut proxy = None;
+        let mut server =
+            Server::<exchange::Recv<i32>, exchange::Recv<i32>, ()>::start_with(config, |p| {
+                proxy = Some(p);
+            });
+        let proxy = proxy.expect("missing proxy");
+
+        let mut first = None;
+        proxy.clone(|proxy| first = Some(Box::pin(proxy.try_connect())));
+        let mut first = first.expect("missing attempt");
+        assert!(first.poll_unpin(&mut cx).is_pending());
+        let mut second = None;
+        proxy.clone(|proxy| second = Some(proxy.try_connect()));
+        let second = block_on(second.expect("missing attempt"));
+        assert_eq!(second.err(), Some(ConnectError::Rejected));
+        assert_eq!(server.shared.pending.load(Ordering::SeqCst), 1);
+
+        let Poll::Ready(Some(Event::Connect { session })) = server.poll_event(&mut cx) else {
+            panic!("expected a connection");
+        };
+        assert_eq!(server.shared.pending.load(Ordering::SeqCst), 0);
+        let Poll::Ready(Ok(client)) = first.poll_unpin(&mut cx) else {
+            panic!("expected an accepted connection");
+        };
+        client.send1(1);
+        assert_eq!(block_on(session.recv1()), 1);
+        let mut connection = None;
+        server.suspend((), |conn| connection = Some(conn));
+
+        let mut third = Box::pin(proxy.try_connect());
+        assert!(third.poll_unpin(&mut cx).is_pending());
+        assert!(server.poll_event(&mut cx).is_pending());
+        let Poll::Ready(third) = third.poll_unpin(&mut cx) else {
+            panic!("expected a rejection");
+        };
+        assert_eq!(third.err(), Some(ConnectError::Rejected));
+        assert_eq!(server.shared.pending.load(Ordering::SeqCst), 0);
+
+        connection.expect("missing connection").resume().send1(0);
+        assert!(matches!(
+            server.poll_event(&mut cx),
+            Poll::Ready(Some(Event::Resume { .. }))
+        ));
+        assert!(matches!(server.poll_event(&mut cx), Poll::Ready(None)));
+    }
+
+    #[test]
+    fn try_connect_reports_shutdown_and_dropped_server() {
+        let mut gone = None;
+        let server = Server::<exchange::Recv<i32>, (), ()>::start(|p| gone = Some(p));
+        drop(server);
+        let gone = block_on(gone.expect("missing proxy").try_connect());
+        assert_eq!(gone.err(), Some(ConnectError::ServerGone));
+
+        let mut shutting_down = None;
+        let mut server = Server::<exchange::Recv<i32>, (), ()>::start(|p| shutting_down = Some(p));
+        server.shutdown();
+        let shutting_down = block_on(shutting_down.expect("missing proxy").try_connect());
+        assert_eq!(shutting_down.err(), Some(ConnectError::ShuttingDown));
+        assert!(matches!(
+            server.poll_event(&mut Context::from_waker(noop_waker_ref())),
+            Poll::Ready(None)
+        ));
+    }
+
+    #[test]
+    fn resumption_cancels_idle_timer() {
+        struct Sleep(Arc<AtomicUsize>);
+
+        impl Drop for Sleep {
+            fn drop(&mut self) {
+                self.0.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+
+        let live = Arc::new(AtomicUsize::new(0));
+        let timer = {
+            let live = Arc::clone(&live);
+            move |_| {
+                live.fetch_add(1, Ordering::SeqCst);
+                let sleep = Sleep(Arc::clone(&live));
+                future::pending::<()>().map(move |()| drop(sleep))
+            }
+        };
+
+        let mut server = TestServer::<()>::start(drop);
+        server.set_idle_timeout(Duration::from_secs(60), timer);
+
+        let (id, connection) = suspend(&mut server, ());
+        let mut connection = Some(connection);
+        for i in 0..100 {
+            connection
+                .take()
+                .expect("missing connection")
+                .resume()
+                .send1(i);
+            assert_eq!(resumed(&mut server), ((), id));
+            server.resuspend(id, (), |conn| connection = Some(conn));
+        }
+        assert!(poll(&mut server).is_pending());
+        assert_eq!(live.load(Ordering::SeqCst), 1);
+        drop(connection);
     }
 
-    fn send(self: Box<Self>, value: T) {
-        self(value)
+    #[test]
+    fn run_passes_events_to_the_handler() {
+        type Connect = exchange::Recv<i32, exchange::Send<Connection<exchange::Send<i32>>>>;
+
+        #[derive(Default)]
+        struct Log(Vec<(&'static str, i32)>);
+
+        impl ServerHandler<Connect, exchange::Recv<i32>, i32> for Log {
+            async fn on_connect(
+                &mut self,
+                mut server: Suspender<'_, Connect, exchange::Recv<i32>, i32>,
+                session: Connect,
+            ) {
+                let (data, client) = session.recv().await;
+                self.0.push(("connect", data));
+                server.suspend(data, |conn| client.send1(conn));
+            }
+
+            async fn on_resume(
+                &mut self,
+                _server: Suspender<'_, Connect, exchange::Recv<i32>, i32>,
+                session: exchange::Recv<i32>,
+                data: i32,
+                _id: ConnectionId,
+            ) {
+                self.0.push(("resume", data + session.recv1().await));
+            }
+
+            async fn on_expire(
+                &mut self,
+                _server: Suspender<'_, Connect, exchange::Recv<i32>, i32>,
+                data: i32,
+                _id: ConnectionId,
+            ) {
+                self.0.push(("expire", data));
+            }
+        }
+
+        let mut proxy = None;
+        let mut server = Server::<Connect, exchange::Recv<i32>, i32>::start(|p| proxy = Some(p));
+        let sleeps = AtomicUsize::new(0);
+        server.set_idle_timeout(Duration::ZERO, move |_| {
+            match sleeps.fetch_add(1, Ordering::SeqCst) {
+                0 => future::pending().left_future(),
+                _ => future::ready(()).right_future(),
+            }
+        });
+
+        let proxy = proxy.expect("missing proxy");
+        let mut first = None;
+        proxy.clone(|proxy| first = Some(proxy.connect()));
+        let second = proxy.connect();
+        let clients = async {
+            let first = first.expect("missing session").send(1).recv1().await;
+            let second = second.send(2).recv1().await;
+            first.resume().send1(10);
+            second
+        };
+        let (log, second) = block_on(future::join(server.run(Log::default()), clients));
+        assert_eq!(
+            log.0,
+            [
+                ("connect", 1),
+                ("connect", 2),
+                ("expire", 2),
+                ("resume", 11)
+            ]
+        );
+        assert_eq!(second.try_resume().err(), Some(ResumeError::Expired));
+    }
+
+    #[test]
+    fn idle_connection_expires() {
+        let mut server = TestServer::<i32>::start(drop);
+        server.set_idle_timeout(Duration::ZERO, |_| future::ready(()));
+
+        let (id, connection) = suspend(&mut server, 1);
+        let Poll::Ready(Some(Event::Expired { data, id: expired })) = poll(&mut server) else {
+            panic!("expected an expiration");
+        };
+        assert_eq!((data, expired), (1, id));
+        assert!(matches!(poll(&mut server), Poll::Ready(None)));
+
+        let resumed = connection.try_resume();
+        assert_eq!(resumed.err(), Some(ResumeError::Expired));
+    }
+
+    #[test]
+    fn groups_track_active_connections() {
+        let mut server = TestServer::<i32>::start(drop);
+        let members = |server: &TestServer<i32>, group| {
+            server
+                .group(group)
+                .map(|(id, data)| (id, data.copied()))
+                .collect::<HashMap<_, _>>()
+        };
+
+        let (a, first) = suspend(&mut server, 1);
+        let (b, second) = suspend(&mut server, 2);
+        let group = server.create_group();
+        assert!(server.join(group, a) && server.join(group, b));
+
+        first.resume().send1(0);
+        assert_eq!(resumed(&mut server), (1, a));
+        assert_eq!(
+            members(&server, group),
+            HashMap::from([(a, None), (b, Some(2))])
+        );
+
+        assert!(poll(&mut server).is_pending());
+        assert_eq!(members(&server, group), HashMap::from([(b, Some(2))]));
+        assert!(!server.join(group, a));
+        assert!(!server.leave(group, a));
+
+        assert!(server.delete_group(group));
+        assert!(!server.delete_group(group));
+        assert!(!server.join(group, b));
+        assert!(members(&server, group).is_empty());
+
+        second.resume().send1(0);
+        assert_eq!(resumed(&mut server), (2, b));
+        assert!(matches!(poll(&mut server), Poll::Ready(None)));
+    }
+
+    #[test]
+    fn expired_connection_leaves_its_groups() {
+        let mut server = TestServer::<i32>::start(drop);
+        server.set_idle_timeout(Duration::ZERO, |_| future::ready(()));
+
+        let (id, connection) = suspend(&mut server, 1);
+        let group = server.create_group();
+        assert!(server.join(group, id));
+        assert!(matches!(
+            poll(&mut server),
+            Poll::Ready(Some(Event::Expired { .. }))
+        ));
+        assert_eq!(server.group(group).count(), 0);
+        assert!(!server.join(group, id));
+        drop(connection);
+    }
+
+    #[test]
+    fn notifications_reach_inboxes_of_active_connections() {
+        let mut server = TestServer::<(), i32>::start(drop);
+        let mut clients = Vec::new();
+        let a = server.suspend_with_inbox((), |conn, inbox| clients.push((conn, inbox)));
+        let b = server.suspend_with_inbox((), |conn, inbox| clients.push((conn, inbox)));
+        let group = server.create_group();
+        assert!(server.join(group, b));
+
+        server.notify_all(1);
+        server.notify_group(group, 2);
+        assert_eq!(server.notify(a, 3), Ok(()));
+
+        let mut inboxes = Vec::new();
+        for (connection, inbox) in clients {
+            connection.resume().send1(0);
+            inboxes.push(inbox);
+        }
+        assert_eq!(resumed(&mut server), ((), a));
+        assert_eq!(resumed(&mut server), ((), b));
+        assert!(matches!(poll(&mut server), Poll::Ready(None)));
+        assert_eq!(server.notify(a, 4), Err(4));
+
+        let notifications = inboxes
+            .into_iter()
+            .map(|inbox| block_on(inbox.fold1(Vec::new(), push)));
+        assert_eq!(notifications.collect::<Vec<_>>(), [vec![1, 3], vec![1, 2]]);
     }
 }
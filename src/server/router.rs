@@ -0,0 +1,243 @@
+use super::{Event, Proxy, Server};
+use crate::{
+    exchange::{Recv, Send},
+    Session,
+};
+use futures::{future, future::BoxFuture, stream::FuturesUnordered, Future, FutureExt, StreamExt};
+use std::{
+    marker,
+    task::{Context, Poll},
+};
+
+/// Dispatches new connections to several servers behind a single [`Proxy`]. The client first sends
+/// a `Route`, and then continues with the `Connect` protocol.
+///
+/// Each connection is passed to a dispatch function, which picks a server by the route and
+/// [links](Session::link) the client's session into a new connection of that server. The servers
+/// may differ in their `Resume` and `ConnectionData` types:
+///
+/// ```ignore
+/// let router = Router::start(
+///     move |key: Key, session| {
+///         match key {
+///             Key::Chat => chat.clone(|proxy| proxy.connect().link(session)),
+///             Key::Game => game.clone(|proxy| proxy.connect().link(session)),
+///         }
+///         future::ready(())
+///     },
+///     |proxy| { /* proxy.connect_keyed(Key::Chat) */ },
+/// );
+/// ```
+///
+/// The route may also be a branch choice carrying the session itself, with `Connect` being `()`. This
+/// way, each server can have its own `Connect` type:
+///
+/// ```ignore
+/// enum Service {
+///     Chat(ChatConnect),
+///     Game(GameConnect),
+/// }
+///
+/// let router = Router::start(
+///     move |service, ()| {
+///         match service {
+///             Service::Chat(session) => chat.clone(|proxy| proxy.connect().link(session)),
+///             Service::Game(session) => game.clone(|proxy| proxy.connect().link(session)),
+///         }
+///         future::ready(())
+///     },
+///     |proxy| { /* proxy.connect().choose(Service::Chat) */ },
+/// );
+/// ```
+///
+/// The future returned by the dispatch function runs concurrently with the other dispatches.
+/// [`Proxy::connect`] panics if the chosen server has [shut down](Server::shutdown) or no longer
+/// exists, taking the whole router down with it. To handle that, move a proxy into the future and
+/// use [`Proxy::try_connect`] instead:
+///
+/// ```ignore
+/// move |key: Key, session| {
+///     let mut proxy = None;
+///     chat.clone(|chat| proxy = Some(chat));
+///     async move {
+///         match proxy.unwrap().try_connect().await {
+///             Ok(chat) => chat.link(session),
+///             Err(error) => { /* end the session according to its protocol */ }
+///         }
+///     }
+/// }
+/// ```
+///
+/// Must not be dropped. Use [`run`](Self::run) to dispatch the connections.
+#[must_use]
+pub struct Router<Route, Connect = ()>
+where
+    Route: marker::Send + 'static,
+    Connect: Session,
+{
+    server: Server<Recv<Route, Connect>, (), ()>,
+    pending: FuturesUnordered<BoxFuture<'static, (Route, Connect)>>,
+    dispatching: FuturesUnordered<BoxFuture<'static, ()>>,
+    dispatch: Box<dyn FnMut(Route, Connect) -> BoxFuture<'static, ()> + marker::Send>,
+}
+
+impl<Route, Connect> Router<Route, Connect>
+where
+    Route: marker::Send + 'static,
+    Connect: Session,
+{
+    /// Creates a new [`Router`] with the provided asynchronous dispatch function, and passes
+    /// a [`Proxy`] to it to the provided closure. Use [`connect_keyed`](Proxy::connect_keyed) to
+    /// connect through the [`Proxy`], or [`connect`](Proxy::connect) followed by
+    /// a [choice](Send::choose).
+    pub fn start<F>(
+        mut dispatch: impl FnMut(Route, Connect) -> F + marker::Send + 'static,
+        f: impl FnOnce(Proxy<Send<Route, Connect::Dual>>),
+    ) -> Self
+    where
+        F: Future<Output = ()> + marker::Send + 'static,
+    {
+        Self {
+            server: Server::start(f),
+            pending: FuturesUnordered::new(),
+            dispatching: FuturesUnordered::new(),
+            dispatch: Box::new(move |route, session| dispatch(route, session).boxed()),
+        }
+    }
+
+    /// Dispatches connections until no more [proxies](Proxy) of the router exist, and all the
+    /// connections have been dispatched. A client that's slow to send its route, or a slow dispatch,
+    /// doesn't hold up the others.
+    pub async fn run(mut self) {
+        future::poll_fn(|cx| self.poll_dispatch(cx)).await
+    }
+
+    fn poll_dispatch(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            while let Poll::Ready(Some((route, session))) = self.pending.poll_next_unpin(cx) {
+                self.dispatching.push((self.dispatch)(route, session));
+            }
+            while let Poll::Ready(Some(())) = self.dispatching.poll_next_unpin(cx) {}
+            match self.server.poll_event(cx) {
+                Poll::Ready(Some(Event::Connect { session })) => {
+                    self.pending.push(session.recv().boxed())
+                }
+                Poll::Ready(Some(Event::Resume { .. } | Event::Expired { .. })) => {
+                    unreachable!("router connections are never suspended")
+                }
+                Poll::Ready(None) if self.pending.is_empty() && self.dispatching.is_empty() => {
+                    return Poll::Ready(())
+                }
+                Poll::Ready(None) | Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::ConnectError;
+    use futures::{channel::oneshot, executor::block_on};
+
+    type Numbers = Server<Recv<i32>, (), ()>;
+
+    fn start_numbers() -> (Numbers, Proxy<Send<i32>>) {
+        let mut proxy = None;
+        let server = Server::start(|p| proxy = Some(p));
+        (server, proxy.expect("missing proxy"))
+    }
+
+    async fn received(mut server: Numbers) -> Vec<i32> {
+        let mut received = Vec::new();
+        while let Some(event) = server.next_event().await {
+            if let Event::Connect { session } = event {
+                received.push(session.recv1().await);
+            }
+        }
+        received
+    }
+
+    #[test]
+    fn routes_by_key() {
+        let (even, even_proxy) = start_numbers();
+        let (odd, odd_proxy) = start_numbers();
+        let mut proxy = None;
+        let router = Router::start(
+            move |even: bool, session: Recv<i32>| {
+                let target = if even { &even_proxy } else { &odd_proxy };
+                target.clone(|proxy| proxy.connect().link(session));
+                future::ready(())
+            },
+            |p| proxy = Some(p),
+        );
+
+        let proxy = proxy.expect("missing proxy");
+        for number in 1..=4 {
+            proxy.clone(|proxy| proxy.connect_keyed(number % 2 == 0).send1(number));
+        }
+        drop(proxy);
+        block_on(router.run());
+        assert_eq!(block_on(received(even)), [2, 4]);
+        assert_eq!(block_on(received(odd)), [1, 3]);
+    }
+
+    #[test]
+    fn routes_by_branch_choice() {
+        enum Service {
+            Even(Recv<i32>),
+            Odd(Recv<i32>),
+        }
+
+        let (even, even_proxy) = start_numbers();
+        let (odd, odd_proxy) = start_numbers();
+        let mut proxy = None;
+        let router = Router::start(
+            move |service, ()| {
+                match service {
+                    Service::Even(session) => even_proxy.clone(|p| p.connect().link(session)),
+                    Service::Odd(session) => odd_proxy.clone(|p| p.connect().link(session)),
+                }
+                future::ready(())
+            },
+            |p| proxy = Some(p),
+        );
+
+        let proxy = proxy.expect("missing proxy");
+        proxy.clone(|proxy| proxy.connect().choose(Service::Odd).send1(1));
+        proxy.connect().choose(Service::Even).send1(2);
+        block_on(router.run());
+        assert_eq!(block_on(received(even)), [2]);
+        assert_eq!(block_on(received(odd)), [1]);
+    }
+
+    #[test]
+    fn dispatch_handles_a_shut_down_server() {
+        let (mut numbers, numbers_proxy) = start_numbers();
+        numbers.shutdown();
+        let (report, failure) = oneshot::channel();
+        let mut report = Some(report);
+        let mut proxy = None;
+        let router = Router::start(
+            move |(), session: Recv<i32>| {
+                let mut target = None;
+                numbers_proxy.clone(|proxy| target = Some(proxy));
+                let report = report.take();
+                async move {
+                    match target.expect("missing proxy").try_connect().await {
+                        Ok(numbers) => numbers.link(session),
+                        Err(error) => {
+                            let _ = report.expect("dispatched twice").send(error);
+                        }
+                    }
+                }
+            },
+            |p| proxy = Some(p),
+        );
+
+        proxy.expect("missing proxy").connect_keyed(()).send1(1);
+        block_on(router.run());
+        assert_eq!(block_on(failure), Ok(ConnectError::ShuttingDown));
+        assert_eq!(block_on(received(numbers)), []);
+    }
+}
@@ -0,0 +1,284 @@
+use super::{Event, Proxy, Server, ServerConfig};
+use crate::Session;
+use futures::{channel::mpsc, future, Stream, StreamExt};
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+/// A [`Server`] split into several [shards](Shard) behind a single [`Proxy`]. Each shard is a
+/// [`Server`] with its own connections and their local data, meant to run on its own task.
+///
+/// New connections are spread across the shards: [`Proxy::connect`] lets them take turns, while
+/// [`Proxy::connect_keyed`] picks the shard by the hash of a key sent first in the initiation
+/// protocol.
+///
+/// The shards can pass messages of type `Message` to each other, see [`Shard::send_to`].
+///
+/// Must not be dropped. Use [`into_shards`](Self::into_shards) to take the shards out.
+#[must_use]
+pub struct ShardedServer<Connect, Resume, ConnectionData, Message, Notification = ()>
+where
+    Connect: Session,
+    Resume: Session,
+{
+    shards: Vec<Shard<Connect, Resume, ConnectionData, Message, Notification>>,
+}
+
+/// One shard of a [`ShardedServer`]. Polls the events of its own [`Server`], along with messages
+/// from the other shards.
+///
+/// Must not be dropped.
+#[must_use]
+pub struct Shard<Connect, Resume, ConnectionData, Message, Notification = ()>
+where
+    Connect: Session,
+    Resume: Session,
+{
+    server: Server<Connect, Resume, ConnectionData, Notification>,
+    index: usize,
+    mailbox: mpsc::UnboundedReceiver<Message>,
+    messages_first: bool,
+    peers: Arc<[mpsc::UnboundedSender<Message>]>,
+}
+
+/// Event of a [shard](Shard): either an [event](Event) of its [`Server`], or a message from another
+/// shard.
+#[must_use]
+pub enum ShardEvent<Connect, Resume, ConnectionData, Message>
+where
+    Connect: Session,
+    Resume: Session,
+{
+    /// Connection initiation, resumption, or expiration event of the shard's [`Server`].
+    Server(Event<Connect, Resume, ConnectionData>),
+
+    /// Message sent by another shard with [`Shard::send_to`] or [`Shard::broadcast`].
+    Message(Message),
+}
+
+impl<Connect, Resume, ConnectionData, Message, Notification>
+    ShardedServer<Connect, Resume, ConnectionData, Message, Notification>
+where
+    Connect: Session,
+    Resume: Session,
+    Notification: Send + 'static,
+{
+    /// Creates a new [`ShardedServer`] with the given number of shards and passes a [`Proxy`] to it
+    /// to the provided closure. See [`Server::start`].
+    ///
+    /// Panics if the number of shards is zero.
+    pub fn start(shards: usize, f: impl FnOnce(Proxy<Connect::Dual>)) -> Self {
+        Self::start_with(ServerConfig::default(), shards, f)
+    }
+
+    /// Creates a new [`ShardedServer`] whose shards use the provided [configuration](ServerConfig).
+    /// The limits apply to each shard separately. See [`start`](Self::start).
+    pub fn start_with(
+        config: ServerConfig,
+        shards: usize,
+        f: impl FnOnce(Proxy<Connect::Dual>),
+    ) -> Self {
+        assert!(shards > 0, "no shards");
+        let (servers, routes): (Vec<_>, Vec<_>) =
+            (0..shards).map(|_| Server::create(config)).unzip();
+        f(Proxy::new(routes));
+        let (peers, mailboxes): (Vec<_>, Vec<_>) = (0..shards).map(|_| mpsc::unbounded()).unzip();
+        let peers: Arc<[_]> = peers.into();
+        Self {
+            shards: servers
+                .into_iter()
+                .zip(mailboxes)
+                .enumerate()
+                .map(|(index, (server, mailbox))| Shard {
+                    server,
+                    index,
+                    mailbox,
+                    messages_first: false,
+                    peers: Arc::clone(&peers),
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the shards for configuration, such as setting an
+    /// [idle timeout](Server::set_idle_timeout).
+    pub fn shards_mut(
+        &mut self,
+    ) -> &mut [Shard<Connect, Resume, ConnectionData, Message, Notification>] {
+        &mut self.shards
+    }
+
+    /// Takes the shards out, ordered by their [indices](Shard::index), to run each on its own task.
+    pub fn into_shards(self) -> Vec<Shard<Connect, Resume, ConnectionData, Message, Notification>> {
+        self.shards
+    }
+}
+
+impl<Connect, Resume, ConnectionData, Message, Notification>
+    Shard<Connect, Resume, ConnectionData, Message, Notification>
+where
+    Connect: Session,
+    Resume: Session,
+    Notification: Send + 'static,
+{
+    /// Returns the index of this shard, from zero up to the number of shards.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the number of shards of the [`ShardedServer`].
+    pub fn shard_count(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// Returns the shard's [`Server`].
+    pub fn server(&self) -> &Server<Connect, Resume, ConnectionData, Notification> {
+        &self.server
+    }
+
+    /// Returns the shard's [`Server`], to [suspend](Server::suspend) connections and access their data.
+    pub fn server_mut(&mut self) -> &mut Server<Connect, Resume, ConnectionData, Notification> {
+        &mut self.server
+    }
+
+    /// Sends a message to the shard with the given index, possibly this one. It comes as a
+    /// [message event](ShardEvent::Message). Returns the message back if there's no such shard, or
+    /// it has finished or been dropped.
+    pub fn send_to(&self, shard: usize, message: Message) -> Result<(), Message> {
+        match self.peers.get(shard) {
+            Some(peer) => peer
+                .unbounded_send(message)
+                .map_err(|error| error.into_inner()),
+            None => Err(message),
+        }
+    }
+
+    /// Sends a message to all the other shards that haven't finished.
+    pub fn broadcast(&self, message: Message)
+    where
+        Message: Clone,
+    {
+        for (index, peer) in self.peers.iter().enumerate() {
+            if index != self.index {
+                let _ = peer.unbounded_send(message.clone());
+            }
+        }
+    }
+
+    /// Waits for the next [event](Event) of the shard's [`Server`], or a message from another shard.
+    /// Returns [`None`] once the shard's [`Server`] finishes, see [`Server::next_event`].
+    #[must_use]
+    pub async fn next_event(
+        &mut self,
+    ) -> Option<ShardEvent<Connect, Resume, ConnectionData, Message>> {
+        future::poll_fn(|cx| self.poll_event(cx)).await
+    }
+
+    /// Polls for the next [event](Event) of the shard's [`Server`], or a message from another shard.
+    /// When both are waiting, they take turns. Produces [`None`] once the shard's [`Server`]
+    /// finishes and all the messages sent before that have been produced. A finished shard doesn't
+    /// accept any more messages.
+    pub fn poll_event(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<ShardEvent<Connect, Resume, ConnectionData, Message>>> {
+        self.messages_first = !self.messages_first;
+        if self.messages_first {
+            if let Poll::Ready(Some(message)) = self.poll_message(cx) {
+                return Poll::Ready(Some(message));
+            }
+            self.poll_server(cx)
+        } else {
+            match self.poll_server(cx) {
+                Poll::Pending => match self.poll_message(cx) {
+                    Poll::Ready(Some(message)) => Poll::Ready(Some(message)),
+                    Poll::Ready(None) | Poll::Pending => Poll::Pending,
+                },
+                event => event,
+            }
+        }
+    }
+
+    fn poll_server(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<ShardEvent<Connect, Resume, ConnectionData, Message>>> {
+        match self.server.poll_event(cx) {
+            Poll::Ready(Some(event)) => Poll::Ready(Some(ShardEvent::Server(event))),
+            Poll::Ready(None) => {
+                self.mailbox.close();
+                self.poll_message(cx)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_message(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<ShardEvent<Connect, Resume, ConnectionData, Message>>> {
+        self.mailbox
+            .poll_next_unpin(cx)
+            .map(|message| message.map(ShardEvent::Message))
+    }
+}
+
+impl<Connect, Resume, ConnectionData, Message, Notification> Stream
+    for Shard<Connect, Resume, ConnectionData, Message, Notification>
+where
+    Connect: Session,
+    Resume: Session,
+    Notification: Send + 'static,
+{
+    type Item = ShardEvent<Connect, Resume, ConnectionData, Message>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_event(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::Recv;
+    use futures::task::noop_waker_ref;
+
+    #[test]
+    fn messages_do_not_starve_server_events() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut proxy = None;
+        let sharded = ShardedServer::<Recv<i32>, (), (), i32>::start(1, |p| proxy = Some(p));
+        let [mut shard] = <[_; 1]>::try_from(sharded.into_shards())
+            .ok()
+            .expect("one shard");
+        for message in 0..10 {
+            assert_eq!(shard.send_to(0, message), Ok(()));
+        }
+        proxy.expect("missing proxy").connect().send1(7);
+
+        let events = [shard.poll_event(&mut cx), shard.poll_event(&mut cx)];
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Poll::Ready(Some(ShardEvent::Server(Event::Connect { .. })))
+        )));
+    }
+
+    #[test]
+    fn finished_shard_produces_sent_messages_then_rejects_more() {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let sharded = ShardedServer::<Recv<i32>, (), (), i32>::start(2, drop);
+        let [mut finished, other] = <[_; 2]>::try_from(sharded.into_shards())
+            .ok()
+            .expect("two shards");
+        assert_eq!(other.send_to(0, 1), Ok(()));
+
+        assert!(matches!(
+            finished.poll_event(&mut cx),
+            Poll::Ready(Some(ShardEvent::Message(1)))
+        ));
+        assert!(matches!(finished.poll_event(&mut cx), Poll::Ready(None)));
+        assert_eq!(other.send_to(0, 2), Err(2));
+    }
+}
@@ -0,0 +1,243 @@
+use super::ConnectionId;
+use std::mem;
+
+/// Generational storage of connection data, indexed by [`ConnectionId`].
+///
+/// A slot is suspended while its connection is suspended, and resumed after a resumption event.
+/// Resumed slots are freed by [`release_resumed`](Self::release_resumed), unless
+/// [resuspended](Self::resuspend) in the meantime. Freeing a slot increments its generation, so
+/// a stale [`ConnectionId`] never refers to a different connection.
+pub(super) struct Slab<D> {
+    slots: Vec<Slot<D>>,
+    free: Vec<usize>,
+    resumed: Vec<ConnectionId>,
+}
+
+struct Slot<D> {
+    generation: u64,
+    state: State<D>,
+}
+
+enum State<D> {
+    Free,
+    Suspended(D),
+    Resumed,
+}
+
+impl<D> Slab<D> {
+    pub(super) fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            resumed: Vec::new(),
+        }
+    }
+
+    /// Stores the data of a new suspended connection.
+    pub(super) fn insert(&mut self, data: D) -> ConnectionId {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    state: State::Free,
+                });
+                self.slots.len() - 1
+            }
+        };
+        let slot = &mut self.slots[index];
+        slot.state = State::Suspended(data);
+        ConnectionId {
+            index,
+            generation: slot.generation,
+        }
+    }
+
+    /// Suspends a resumed connection again, keeping its identifier. Gives the data back if the
+    /// connection isn't resumed.
+    pub(super) fn resuspend(&mut self, id: ConnectionId, data: D) -> Result<(), D> {
+        match self.slot_mut(id) {
+            Some(slot) if matches!(slot.state, State::Resumed) => {
+                slot.state = State::Suspended(data);
+                Ok(())
+            }
+            _ => Err(data),
+        }
+    }
+
+    /// Takes the data of a suspended connection, marking it resumed.
+    pub(super) fn resume(&mut self, id: ConnectionId) -> Option<D> {
+        let slot = self.slot_mut(id)?;
+        match mem::replace(&mut slot.state, State::Resumed) {
+            State::Suspended(data) => {
+                self.resumed.push(id);
+                Some(data)
+            }
+            state => {
+                slot.state = state;
+                None
+            }
+        }
+    }
+
+    /// Takes the data of a suspended connection and frees its slot.
+    pub(super) fn remove(&mut self, id: ConnectionId) -> Option<D> {
+        let slot = self.slot_mut(id)?;
+        match mem::replace(&mut slot.state, State::Free) {
+            State::Suspended(data) => {
+                slot.generation = slot.generation.wrapping_add(1);
+                self.free.push(id.index);
+                Some(data)
+            }
+            state => {
+                slot.state = state;
+                None
+            }
+        }
+    }
+
+    /// Frees the slots of all the resumed connections that haven't been suspended again. Returns the
+    /// identifiers of the freed slots.
+    pub(super) fn release_resumed(&mut self) -> Vec<ConnectionId> {
+        let mut released = mem::take(&mut self.resumed);
+        released.retain(|id| {
+            let slot = &mut self.slots[id.index];
+            if slot.generation == id.generation && matches!(slot.state, State::Resumed) {
+                slot.state = State::Free;
+                slot.generation = slot.generation.wrapping_add(1);
+                self.free.push(id.index);
+                true
+            } else {
+                false
+            }
+        });
+        released
+    }
+
+    /// Returns the number of occupied slots.
+    pub(super) fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the connection is suspended or resumed.
+    pub(super) fn contains(&self, id: ConnectionId) -> bool {
+        self.slots.get(id.index).is_some_and(|slot| {
+            slot.generation == id.generation && !matches!(slot.state, State::Free)
+        })
+    }
+
+    pub(super) fn get(&self, id: ConnectionId) -> Option<&D> {
+        match self.slots.get(id.index) {
+            Some(Slot {
+                generation,
+                state: State::Suspended(data),
+            }) if *generation == id.generation => Some(data),
+            _ => None,
+        }
+    }
+
+    pub(super) fn get_mut(&mut self, id: ConnectionId) -> Option<&mut D> {
+        match self.slot_mut(id) {
+            Some(Slot {
+                state: State::Suspended(data),
+                ..
+            }) => Some(data),
+            _ => None,
+        }
+    }
+
+    /// Returns an iterator over all the suspended connections.
+    pub(super) fn iter(&self) -> impl Iterator<Item = (ConnectionId, &D)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| match &slot.state {
+                State::Suspended(data) => Some((
+                    ConnectionId {
+                        index,
+                        generation: slot.generation,
+                    },
+                    data,
+                )),
+                _ => None,
+            })
+    }
+
+    fn slot_mut(&mut self, id: ConnectionId) -> Option<&mut Slot<D>> {
+        self.slots
+            .get_mut(id.index)
+            .filter(|slot| slot.generation == id.generation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_slot_is_reused_with_new_generation() {
+        let mut slab = Slab::new();
+        let a = slab.insert("a");
+        assert_eq!(slab.remove(a), Some("a"));
+        assert_eq!(slab.remove(a), None);
+
+        let b = slab.insert("b");
+        assert_eq!(b.index, a.index);
+        assert_ne!(b, a);
+        assert!(!slab.contains(a));
+        assert_eq!(slab.get(a), None);
+        assert_eq!(slab.get(b), Some(&"b"));
+    }
+
+    #[test]
+    fn resuspend_keeps_identifier_of_resumed_connection() {
+        let mut slab = Slab::new();
+        let id = slab.insert(1);
+        assert_eq!(slab.resume(id), Some(1));
+        assert_eq!(slab.resume(id), None);
+        assert!(slab.contains(id));
+        assert_eq!(slab.get(id), None);
+
+        assert_eq!(slab.resuspend(id, 2), Ok(()));
+        assert_eq!(slab.resuspend(id, 3), Err(3));
+        assert_eq!(slab.release_resumed(), []);
+        assert_eq!(slab.get(id), Some(&2));
+    }
+
+    #[test]
+    fn insert_while_resumed_creates_new_connection() {
+        let mut slab = Slab::new();
+        let a = slab.insert("a");
+        assert_eq!(slab.resume(a), Some("a"));
+
+        let b = slab.insert("b");
+        assert_ne!(b, a);
+        assert_eq!(slab.resuspend(a, "a"), Ok(()));
+        assert_eq!(slab.release_resumed(), []);
+        assert_eq!(slab.iter().collect::<Vec<_>>(), [(a, &"a"), (b, &"b")]);
+    }
+
+    #[test]
+    fn release_resumed_frees_connections_not_resuspended() {
+        let mut slab = Slab::new();
+        let a = slab.insert("a");
+        let b = slab.insert("b");
+        assert_eq!(slab.resume(a), Some("a"));
+        assert_eq!(slab.resume(b), Some("b"));
+        assert_eq!(slab.resuspend(b, "b"), Ok(()));
+
+        assert_eq!(slab.release_resumed(), [a]);
+        assert_eq!(slab.len(), 1);
+        assert!(!slab.contains(a));
+        assert_eq!(slab.resuspend(a, "a"), Err("a"));
+        assert_eq!(slab.iter().collect::<Vec<_>>(), [(b, &"b")]);
+
+        let c = slab.insert("c");
+        assert_eq!(c.index, a.index);
+        assert_ne!(c, a);
+    }
+}